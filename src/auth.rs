@@ -0,0 +1,63 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use crate::error::RigCtlError;
+use std::fmt;
+use std::fmt::{Debug, Formatter};
+
+// A password is sent through rigctld's \password command, as configured with
+// `rigctld -A`. rigctld has no command that takes a shared secret, so those
+// credentials fail with AuthenticationUnsupported instead of being sent
+#[derive(Clone, PartialEq, Eq)]
+pub enum Credentials {
+    Password(String),
+    SharedSecret(String),
+}
+
+impl Credentials {
+    pub fn command(&self) -> Result<String, RigCtlError> {
+        match self {
+            Credentials::Password(password) => Ok(format!("password {}", password)),
+            Credentials::SharedSecret(_) => Err(RigCtlError::AuthenticationUnsupported("shared secret".to_string())),
+        }
+    }
+}
+
+impl Debug for Credentials {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Credentials::Password(_) => { write!(f, "Password(***)") }
+            Credentials::SharedSecret(_) => { write!(f, "SharedSecret(***)") }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command() {
+        assert_eq!(Credentials::Password("secret".to_string()).command().unwrap(), "password secret");
+        assert!(matches!(Credentials::SharedSecret("secret".to_string()).command(), Err(RigCtlError::AuthenticationUnsupported(_))));
+    }
+
+    #[test]
+    fn test_debug_hides_secret() {
+        assert_eq!(format!("{:?}", Credentials::SharedSecret("secret".to_string())), "SharedSecret(***)");
+    }
+}
@@ -0,0 +1,180 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use crate::error::RigCtlError;
+use crate::mode::Mode;
+use crate::protocol::{Command, Response};
+use crate::ptt::PTT;
+use crate::vfo::VFO;
+use crate::RigCtlClient;
+
+pub struct Batch<'a> {
+    client: &'a mut RigCtlClient,
+    commands: Vec<Command>,
+}
+
+impl<'a> Batch<'a> {
+    pub(crate) fn new(client: &'a mut RigCtlClient) -> Self {
+        Self {
+            client,
+            commands: Vec::new(),
+        }
+    }
+
+    pub fn get_info(self) -> Self {
+        self.push(Command::GetInfo)
+    }
+
+    pub fn get_mode(self, vfo: VFO) -> Self {
+        self.push(Command::GetMode(vfo))
+    }
+
+    pub fn get_freq(self, vfo: VFO) -> Self {
+        self.push(Command::GetFreq(vfo))
+    }
+
+    pub fn get_vfo(self) -> Self {
+        self.push(Command::GetVfo)
+    }
+
+    pub fn get_split_vfo(self) -> Self {
+        self.push(Command::GetSplitVfo)
+    }
+
+    pub fn get_split_mode(self, vfo: VFO) -> Self {
+        self.push(Command::GetSplitMode(vfo))
+    }
+
+    pub fn get_split_freq(self, vfo: VFO) -> Self {
+        self.push(Command::GetSplitFreq(vfo))
+    }
+
+    pub fn set_ptt(self, vfo: VFO, ptt: PTT) -> Self {
+        self.push(Command::SetPtt(vfo, ptt))
+    }
+
+    pub fn get_ptt(self, vfo: VFO) -> Self {
+        self.push(Command::GetPtt(vfo))
+    }
+
+    pub fn set_freq(self, vfo: VFO, frequency: u64) -> Self {
+        self.push(Command::SetFreq(vfo, frequency))
+    }
+
+    pub fn set_mode(self, vfo: VFO, mode: Mode, passband: u64) -> Self {
+        self.push(Command::SetMode(vfo, mode, passband))
+    }
+
+    pub fn get_level(self, vfo: VFO, level: &'static str) -> Self {
+        self.push(Command::GetLevel(vfo, level))
+    }
+
+    pub fn get_rit(self, vfo: VFO) -> Self {
+        self.push(Command::GetRit(vfo))
+    }
+
+    pub fn get_xit(self, vfo: VFO) -> Self {
+        self.push(Command::GetXit(vfo))
+    }
+
+    pub fn get_cache(self) -> Self {
+        self.push(Command::GetCache)
+    }
+
+    pub fn set_cache(self, timeout: u64) -> Self {
+        self.push(Command::SetCache(timeout))
+    }
+
+    pub fn push(mut self, command: Command) -> Self {
+        self.commands.push(command);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    pub async fn execute(self) -> Result<Vec<Result<Response, RigCtlError>>, RigCtlError> {
+        if self.commands.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let lines = self.commands.iter()
+            .map(|command| command.to_string())
+            .collect::<Vec<String>>();
+        let replies = self.client.execute_commands(&lines).await?;
+
+        Ok(replies.into_iter()
+            .zip(self.commands)
+            .map(|(reply, command)| reply.and_then(|line| command.parse_response(&line)))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{get_freq, get_mode};
+    use crate::error::ReturnCode;
+    use crate::mock::MockServer;
+
+    #[tokio::test]
+    async fn test_batch_demultiplexes_replies() {
+        let server = MockServer::start().await.unwrap();
+        server.inject_error("get_split_vfo", ReturnCode::ENAVAIL);
+        let mut client = server.client();
+        client.connect().await.unwrap();
+
+        let actual = client.batch()
+            .get_freq(VFO::VFOA)
+            .get_split_vfo()
+            .get_mode(VFO::VFOA)
+            .execute()
+            .await
+            .unwrap();
+
+        assert_eq!(actual.len(), 3);
+        assert_eq!(actual[0].as_ref().unwrap(), &Response::Freq(get_freq::Response { query_vfo: VFO::VFOA, frequency: 14074000u64 }));
+        assert!(matches!(actual[1], Err(RigCtlError::HamlibError(ReturnCode::ENAVAIL))));
+        assert_eq!(actual[2].as_ref().unwrap(), &Response::Mode(get_mode::Response { query_vfo: VFO::VFOA, mode: Mode::USB, passband: 3000u64 }));
+    }
+
+    #[cfg(feature = "transcript")]
+    #[tokio::test]
+    async fn test_rate_limited_batch_waits_for_replies() {
+        use crate::rate_limit::RateLimit;
+        use crate::transcript::{Direction, Entry, ReplayTransport};
+        use std::time::Duration;
+
+        let entries = vec![
+            Entry::new(Direction::Sent, b"|\\get_freq VFOA\n"),
+            Entry::new(Direction::Received, b"get_freq: VFOA|Frequency: 14074000|RPRT 0\n"),
+            Entry::new(Direction::Sent, b"|\\get_vfo\n"),
+            Entry::new(Direction::Received, b"get_vfo: currVFO|VFO: VFOA|RPRT 0\n"),
+        ];
+
+        let mut client = RigCtlClient::from_stream(ReplayTransport::new(entries), Some(100));
+        client.set_rate_limit(Some(RateLimit::new(Duration::from_millis(10))));
+
+        let actual = client.batch().get_freq(VFO::VFOA).get_vfo().execute().await.unwrap();
+        assert!(actual.iter().all(|reply| reply.is_ok()));
+    }
+}
@@ -0,0 +1,193 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use hamlib_client::error::RigCtlError;
+use hamlib_client::mode::Mode;
+use hamlib_client::ptt::PTT;
+use hamlib_client::vfo::VFO;
+use hamlib_client::RigCtlClient;
+use serde_json::json;
+use std::str::FromStr;
+
+pub struct Output {
+    pub text: String,
+    pub json: serde_json::Value,
+}
+
+impl Output {
+    fn new(text: String, json: serde_json::Value) -> Self {
+        Self { text, json }
+    }
+
+    fn done() -> Self {
+        Self::new(String::new(), json!({ "result": "ok" }))
+    }
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum PttState {
+    On,
+    Off,
+    Mic,
+    Data,
+}
+
+impl From<PttState> for PTT {
+    fn from(value: PttState) -> Self {
+        match value {
+            PttState::On => PTT::TX,
+            PttState::Off => PTT::RX,
+            PttState::Mic => PTT::TXMic,
+            PttState::Data => PTT::TXData,
+        }
+    }
+}
+
+#[derive(Clone, clap::Subcommand)]
+pub enum Action {
+    /// Show the rig description
+    Info,
+    /// Show the frequency in Hz
+    GetFreq,
+    /// Set the frequency in Hz
+    SetFreq { frequency: u64 },
+    /// Show mode and passband
+    GetMode,
+    /// Set mode and passband, 0 selects the default passband
+    SetMode {
+        #[arg(value_parser = parse_mode)]
+        mode: Mode,
+        #[arg(default_value_t = 0)]
+        passband: u64,
+    },
+    /// Show the current VFO
+    GetVfo,
+    /// Show split state and transmit VFO
+    GetSplitVfo,
+    /// Show the transmit frequency in split
+    GetSplitFreq,
+    /// Show the transmit mode in split
+    GetSplitMode,
+    /// Show the PTT state, or key and unkey the rig
+    Ptt {
+        #[arg(value_enum)]
+        state: Option<PttState>,
+    },
+    /// Show the rigctld cache timeout in milliseconds
+    GetCache,
+    /// Set the rigctld cache timeout in milliseconds
+    SetCache { timeout: u64 },
+    /// Send any long-form command and print the reply fields
+    Raw {
+        command: String,
+        args: Vec<String>,
+    },
+}
+
+impl Action {
+    pub async fn execute(&self, client: &mut RigCtlClient, vfo: VFO) -> Result<Output, RigCtlError> {
+        let output = match self {
+            Action::Info => {
+                let response = client.get_info().await?;
+                Output::new(response.info.clone(), json!({ "info": response.info }))
+            }
+            Action::GetFreq => {
+                let response = client.get_freq(vfo).await?;
+                Output::new(response.frequency.to_string(), json!({ "vfo": vfo.to_string(), "frequency": response.frequency }))
+            }
+            Action::SetFreq { frequency } => {
+                client.set_freq(vfo, *frequency).await?;
+                Output::done()
+            }
+            Action::GetMode => {
+                let response = client.get_mode(vfo).await?;
+                Output::new(
+                    format!("{} {}", response.mode, response.passband),
+                    json!({ "vfo": vfo.to_string(), "mode": response.mode.to_string(), "passband": response.passband }),
+                )
+            }
+            Action::SetMode { mode, passband } => {
+                client.set_mode(vfo, *mode, *passband).await?;
+                Output::done()
+            }
+            Action::GetVfo => {
+                let response = client.get_vfo().await?;
+                Output::new(response.vfo.to_string(), json!({ "vfo": response.vfo.to_string() }))
+            }
+            Action::GetSplitVfo => {
+                let response = client.get_split_vfo().await?;
+                Output::new(
+                    format!("{} {}", response.split as u8, response.tx_vfo),
+                    json!({ "split": response.split, "tx_vfo": response.tx_vfo.to_string() }),
+                )
+            }
+            Action::GetSplitFreq => {
+                let response = client.get_split_freq(vfo).await?;
+                Output::new(response.frequency.to_string(), json!({ "vfo": vfo.to_string(), "tx_frequency": response.frequency }))
+            }
+            Action::GetSplitMode => {
+                let response = client.get_split_mode(vfo).await?;
+                Output::new(
+                    format!("{} {}", response.mode, response.passband),
+                    json!({ "vfo": vfo.to_string(), "tx_mode": response.mode.to_string(), "tx_passband": response.passband }),
+                )
+            }
+            Action::Ptt { state: None } => {
+                let response = client.get_ptt(vfo).await?;
+                Output::new(response.ptt.to_string(), json!({ "vfo": vfo.to_string(), "ptt": response.ptt.to_string() }))
+            }
+            Action::Ptt { state: Some(state) } => {
+                client.set_ptt(vfo, PTT::from(*state)).await?;
+                Output::done()
+            }
+            Action::GetCache => {
+                let response = client.get_cache().await?;
+                Output::new(response.timeout.to_string(), json!({ "timeout": response.timeout }))
+            }
+            Action::SetCache { timeout } => {
+                client.set_cache(*timeout).await?;
+                Output::done()
+            }
+            Action::Raw { command, args } => {
+                let args = args.iter().map(String::as_str).collect::<Vec<&str>>();
+                let response = client.execute_raw(command, &args).await?;
+                if !response.is_ok() {
+                    return Err(RigCtlError::HamlibError(response.code.into()));
+                }
+
+                let text = response.fields.iter()
+                    .map(|field| field.value.as_str())
+                    .collect::<Vec<&str>>()
+                    .join("\n");
+                let fields = response.fields.iter()
+                    .map(|field| (field.name.clone(), json!(field.value)))
+                    .collect::<serde_json::Map<String, serde_json::Value>>();
+                Output::new(text, json!({ "command": response.command, "fields": fields }))
+            }
+        };
+
+        Ok(output)
+    }
+}
+
+pub fn parse_vfo(value: &str) -> Result<VFO, String> {
+    VFO::from_str(value).map_err(|e| e.to_string())
+}
+
+pub fn parse_mode(value: &str) -> Result<Mode, String> {
+    Mode::from_str(&value.to_uppercase()).map_err(|e| e.to_string())
+}
@@ -0,0 +1,222 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+mod commands;
+mod repl;
+
+use crate::commands::{Action, Output};
+use clap::Parser;
+use hamlib_client::error::RigCtlError;
+use hamlib_client::vfo::VFO;
+use hamlib_client::RigCtlClient;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader};
+use std::process::ExitCode;
+use std::time::Duration;
+
+#[derive(Parser)]
+#[command(version, about = "Control a rig through rigctld")]
+struct Args {
+    /// rigctld host
+    #[arg(long, global = true, default_value = "localhost")]
+    host: String,
+
+    /// rigctld port
+    #[arg(long, global = true, default_value_t = hamlib_client::builder::DEFAULT_PORT)]
+    port: u16,
+
+    /// Command timeout in milliseconds
+    #[arg(long, global = true, default_value_t = 1000)]
+    timeout: u64,
+
+    /// rigctld password
+    #[arg(long, global = true)]
+    password: Option<String>,
+
+    /// VFO targeted by commands that take one
+    #[arg(long, global = true, default_value = "VFOA", value_parser = commands::parse_vfo)]
+    vfo: VFO,
+
+    /// Print results as JSON, one object per command
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Status refresh interval of the interactive mode in milliseconds
+    #[arg(long, global = true, default_value_t = 1000)]
+    status_interval: u64,
+
+    /// Without a command, start the interactive mode
+    #[command(subcommand)]
+    action: Option<Command>,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    #[command(flatten)]
+    Action(Action),
+
+    /// Run one command per line from a file, or from standard input with "-"
+    Batch {
+        file: String,
+
+        /// Continue after a failing command
+        #[arg(long)]
+        keep_going: bool,
+    },
+
+    /// Interactive mode with completion, history and a live status line
+    Repl,
+}
+
+// A single line of a batch file, parsed with the same syntax as the
+// command line
+#[derive(Parser)]
+#[command(no_binary_name = true)]
+struct BatchLine {
+    #[command(subcommand)]
+    action: Action,
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let mut builder = RigCtlClient::builder(&args.host)
+        .port(args.port)
+        .command_timeout(Duration::from_millis(args.timeout));
+    if let Some(password) = &args.password {
+        builder = builder.password(password);
+    }
+    let mut client = builder.build();
+
+    if let Err(e) = client.connect().await {
+        report(&args, &e);
+        return ExitCode::from(exit_code(&e));
+    }
+
+    let result = match &args.action {
+        Some(Command::Action(action)) => run(&args, &mut client, action).await,
+        Some(Command::Batch { file, keep_going }) => batch(&args, &mut client, file, *keep_going).await,
+        Some(Command::Repl) | None => repl::run(&args, &mut client, Duration::from_millis(args.status_interval)).await,
+    };
+
+    client.disconnect();
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => ExitCode::from(exit_code(&e)),
+    }
+}
+
+async fn run(args: &Args, client: &mut RigCtlClient, action: &Action) -> Result<(), RigCtlError> {
+    match action.execute(client, args.vfo).await {
+        Ok(output) => {
+            print(args, &output);
+            Ok(())
+        }
+        Err(e) => {
+            report(args, &e);
+            Err(e)
+        }
+    }
+}
+
+async fn batch(args: &Args, client: &mut RigCtlClient, file: &str, keep_going: bool) -> Result<(), RigCtlError> {
+    let reader: Box<dyn BufRead> = match file {
+        "-" => Box::new(BufReader::new(io::stdin())),
+        path => Box::new(BufReader::new(File::open(path)?)),
+    };
+
+    let mut first_error = None;
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let result = match BatchLine::try_parse_from(line.split_whitespace()) {
+            Ok(parsed) => run(args, client, &parsed.action).await,
+            Err(e) => {
+                let error = RigCtlError::InvalidArgument(format!("line {}: {}", number + 1, e.to_string().lines().next().unwrap_or_default()));
+                report(args, &error);
+                Err(error)
+            }
+        };
+
+        if let Err(e) = result {
+            if !keep_going {
+                return Err(e);
+            }
+            first_error.get_or_insert(e);
+        }
+    }
+
+    first_error.map_or(Ok(()), Err)
+}
+
+fn print(args: &Args, output: &Output) {
+    match args.json {
+        true => println!("{}", output.json),
+        false if output.text.is_empty() => {}
+        false => println!("{}", output.text),
+    }
+}
+
+fn report(args: &Args, error: &RigCtlError) {
+    match args.json {
+        true => println!("{}", serde_json::json!({ "error": error.to_string(), "code": hamlib_code(error) })),
+        false => eprintln!("Error: {}", error),
+    }
+}
+
+fn hamlib_code(error: &RigCtlError) -> Option<i32> {
+    match error {
+        RigCtlError::HamlibError(code) => Some(code.code()),
+        _ => None,
+    }
+}
+
+// Hamlib errors exit with the absolute value of their RPRT code, other
+// failures with the code of the closest Hamlib error
+fn exit_code(error: &RigCtlError) -> u8 {
+    let code: i32 = match error {
+        RigCtlError::HamlibError(code) => code.code(),
+        RigCtlError::InvalidArgument(_) => -1,
+        RigCtlError::CommunicationTimeout => -5,
+        RigCtlError::ConnectionError(_) | RigCtlError::NotConnected | RigCtlError::AlreadyConnected => -6,
+        RigCtlError::ResponseParsing(_) | RigCtlError::RawDataError(_) => -8,
+        RigCtlError::AuthenticationFailed => -19,
+        RigCtlError::AuthenticationUnsupported(_) => -4,
+    };
+    code.unsigned_abs().clamp(1, 125) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hamlib_client::error::ReturnCode;
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(exit_code(&RigCtlError::HamlibError(ReturnCode::ENAVAIL)), 11);
+        assert_eq!(exit_code(&RigCtlError::CommunicationTimeout), 5);
+        assert_eq!(exit_code(&RigCtlError::NotConnected), 6);
+        assert_eq!(exit_code(&RigCtlError::AuthenticationFailed), ReturnCode::ESECURITY.code().unsigned_abs() as u8);
+    }
+}
@@ -0,0 +1,326 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use crate::commands;
+use crate::commands::PttState;
+use crate::{print, report, Args, BatchLine};
+use clap::{CommandFactory, Parser, ValueEnum};
+use hamlib_client::error::RigCtlError;
+use hamlib_client::mode::Mode;
+use hamlib_client::protocol::Response;
+use hamlib_client::ptt::PTT;
+use hamlib_client::vfo::VFO;
+use hamlib_client::RigCtlClient;
+use lazy_static::lazy_static;
+use regex::Regex;
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, ExternalPrinter, Helper};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+use tokio::time;
+
+const BUILTINS: [&str; 5] = ["help", "status", "vfo", "quit", "exit"];
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Status {
+    frequency: Option<u64>,
+    mode: Option<Mode>,
+    ptt: Option<PTT>,
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.frequency {
+            Some(frequency) => write!(f, "{:.6} MHz", frequency as f64 / 1e6)?,
+            None => write!(f, "-")?,
+        }
+        match self.mode {
+            Some(mode) => write!(f, " {}", mode)?,
+            None => write!(f, " -")?,
+        }
+        match self.ptt {
+            Some(PTT::RX) => write!(f, " RX"),
+            Some(_) => write!(f, " TX"),
+            None => write!(f, " -"),
+        }
+    }
+}
+
+struct ReplHelper {
+    commands: Vec<String>,
+    values: Vec<String>,
+    vfos: Vec<String>,
+}
+
+impl ReplHelper {
+    fn new() -> Self {
+        let mut commands = BatchLine::command()
+            .get_subcommands()
+            .map(|command| command.get_name().to_string())
+            .collect::<Vec<String>>();
+        commands.extend(BUILTINS.map(String::from));
+
+        let mut values = Mode::ALL.iter().map(Mode::to_string).collect::<Vec<String>>();
+        values.extend(PttState::value_variants().iter().filter_map(|state| state.to_possible_value()).map(|value| value.get_name().to_string()));
+
+        let vfos = VFO::ALL.iter().map(VFO::to_string).collect::<Vec<String>>();
+
+        Self { commands, values, vfos }
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos].rfind(char::is_whitespace).map_or(0, |index| index + 1);
+        let word = line[start..pos].to_lowercase();
+        let candidates = match (start, line[..start].split_whitespace().next()) {
+            (0, _) => &self.commands,
+            (_, Some("vfo")) => &self.vfos,
+            _ => &self.values,
+        };
+
+        let matches = candidates.iter()
+            .filter(|candidate| candidate.to_lowercase().starts_with(&word))
+            .map(|candidate| Pair { display: candidate.clone(), replacement: candidate.clone() })
+            .collect();
+
+        Ok((start, matches))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+// Reads lines on a dedicated thread, since the editor blocks, and runs them
+// here between status polls; the reader waits for each command to finish
+// before prompting again
+pub async fn run(args: &Args, client: &mut RigCtlClient, interval: Duration) -> Result<(), RigCtlError> {
+    let mut editor = Editor::<ReplHelper, DefaultHistory>::new()
+        .map_err(|e| RigCtlError::InvalidArgument(format!("Unable to start interactive mode: {}", e)))?;
+    editor.set_helper(Some(ReplHelper::new()));
+
+    let history = history_path();
+    if let Some(path) = &history {
+        let _ = editor.load_history(path);
+    }
+
+    let status = Arc::new(std::sync::Mutex::new(Status::default()));
+    let mut printer = editor.create_external_printer().ok();
+
+    let (line_tx, mut line_rx) = tokio::sync::mpsc::channel::<String>(1);
+    let (done_tx, done_rx) = mpsc::channel::<()>();
+    let prompt_status = status.clone();
+    let reader = std::thread::spawn(move || {
+        loop {
+            let prompt = format!("[{}] > ", prompt_status.lock().map(|status| status.to_string()).unwrap_or_default());
+            match editor.readline(&prompt) {
+                Ok(line) => {
+                    let _ = editor.add_history_entry(line.as_str());
+                    if line_tx.blocking_send(line).is_err() || done_rx.recv().is_err() {
+                        break;
+                    }
+                }
+                Err(ReadlineError::Interrupted) => continue,
+                Err(_) => break,
+            }
+        }
+
+        if let Some(path) = &history {
+            let _ = editor.save_history(path);
+        }
+    });
+
+    let mut ticker = time::interval(interval);
+    ticker.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+    let mut vfo = args.vfo;
+
+    loop {
+        tokio::select! {
+            line = line_rx.recv() => {
+                let Some(line) = line else { break };
+                if !execute(args, client, &status, &mut vfo, line.trim()).await {
+                    break;
+                }
+                let _ = done_tx.send(());
+            }
+            _ = ticker.tick() => {
+                let current = poll(client, vfo).await;
+                let changed = match status.lock() {
+                    Ok(mut status) if *status != current => {
+                        *status = current.clone();
+                        true
+                    }
+                    _ => false,
+                };
+
+                if let Some(printer) = printer.as_mut().filter(|_| changed) {
+                    let _ = printer.print(format!("* {}", current));
+                }
+            }
+        }
+    }
+
+    drop(done_tx);
+    drop(line_rx);
+    let _ = tokio::task::spawn_blocking(move || reader.join()).await;
+
+    Ok(())
+}
+
+async fn execute(args: &Args, client: &mut RigCtlClient, status: &std::sync::Mutex<Status>, vfo: &mut VFO, line: &str) -> bool {
+    // "vfo VFOB" targets VFOB from now on, "vfo" shows the current target
+    let mut words = line.split_whitespace();
+    if words.next() == Some("vfo") {
+        match words.next().map(commands::parse_vfo) {
+            None => println!("{}", vfo),
+            Some(Ok(selected)) => *vfo = selected,
+            Some(Err(e)) => report(args, &RigCtlError::InvalidArgument(e)),
+        }
+        return true;
+    }
+
+    match line {
+        "" => return true,
+        "quit" | "exit" => return false,
+        "status" => {
+            println!("{}", status.lock().map(|status| status.to_string()).unwrap_or_default());
+            return true;
+        }
+        "help" => {
+            let _ = BatchLine::command().override_usage("<COMMAND> [ARGS]").print_help();
+            println!("\nAlso: a frequency in MHz with an optional mode suffix (14.074u, 7.03c), vfo <VFO> to change the target VFO, {}", BUILTINS.join(", "));
+            return true;
+        }
+        _ => {}
+    }
+
+    if let Some((frequency, mode)) = parse_shorthand(line) {
+        let mut batch = client.batch().set_freq(*vfo, frequency);
+        if let Some(mode) = mode {
+            batch = batch.set_mode(*vfo, mode, 0);
+        }
+
+        let result = batch.execute().await
+            .and_then(|results| results.into_iter().find(Result::is_err).unwrap_or(Ok(Response::SetFreq)));
+        if let Err(e) = result {
+            report(args, &e);
+        }
+        return true;
+    }
+
+    match BatchLine::try_parse_from(line.split_whitespace()) {
+        Ok(parsed) => match parsed.action.execute(client, *vfo).await {
+            Ok(output) => print(args, &output),
+            Err(e) => report(args, &e),
+        },
+        Err(e) => {
+            let _ = e.print();
+        }
+    }
+
+    true
+}
+
+async fn poll(client: &mut RigCtlClient, vfo: VFO) -> Status {
+    let mut status = Status::default();
+    let results = client.batch().get_freq(vfo).get_mode(vfo).get_ptt(vfo).execute().await;
+    for result in results.into_iter().flatten() {
+        match result {
+            Ok(Response::Freq(response)) => status.frequency = Some(response.frequency),
+            Ok(Response::Mode(response)) => status.mode = Some(response.mode),
+            Ok(Response::PttState(response)) => status.ptt = Some(response.ptt),
+            _ => {}
+        }
+    }
+
+    status
+}
+
+// "14.074" tunes to 14.074 MHz, "14.074u" also selects USB
+fn parse_shorthand(line: &str) -> Option<(u64, Option<Mode>)> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"^(?P<mhz>[0-9]+(?:\.[0-9]*)?)(?P<mode>[a-zA-Z]+)?$").unwrap();
+    }
+
+    let captures = RE.captures(line)?;
+    let mhz = captures.name("mhz")?.as_str().parse::<f64>().ok()?;
+    let mode = match captures.name("mode").map(|mode| mode.as_str().to_uppercase()) {
+        None => None,
+        Some(suffix) => Some(match suffix.as_str() {
+            "U" => Mode::USB,
+            "L" => Mode::LSB,
+            "C" => Mode::CW,
+            "A" => Mode::AM,
+            "F" => Mode::FM,
+            "R" => Mode::RTTY,
+            "D" => Mode::PKTUSB,
+            other => Mode::from_str(other).ok()?,
+        }),
+    };
+
+    Some(((mhz * 1e6).round() as u64, mode))
+}
+
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".hamlib_client_history"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_shorthand() {
+        assert_eq!(parse_shorthand("14.074u"), Some((14074000, Some(Mode::USB))));
+        assert_eq!(parse_shorthand("7.0305CW"), Some((7030500, Some(Mode::CW))));
+        assert_eq!(parse_shorthand("144"), Some((144000000, None)));
+        assert_eq!(parse_shorthand("14.074x"), None);
+        assert_eq!(parse_shorthand("get-freq"), None);
+    }
+
+    #[test]
+    fn test_complete() {
+        let helper = ReplHelper::new();
+        let history = DefaultHistory::new();
+        let context = Context::new(&history);
+
+        let (start, matches) = helper.complete("vfo vf", 6, &context).unwrap();
+        assert_eq!(start, 4);
+        assert_eq!(matches.iter().map(|pair| pair.replacement.as_str()).collect::<Vec<&str>>(), vec!["VFOA", "VFOB", "VFOC", "VFO"]);
+
+        let (_, matches) = helper.complete("set-mode us", 11, &context).unwrap();
+        assert_eq!(matches.iter().map(|pair| pair.replacement.as_str()).collect::<Vec<&str>>(), vec!["USB"]);
+    }
+}
@@ -0,0 +1,85 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use clap::Parser;
+use hamlib_client::connection::ReconnectPolicy;
+use hamlib_client::handle::RigCtlHandle;
+use hamlib_client::proxy::{ProxyConfig, RigProxy};
+use hamlib_client::RigCtlClient;
+use std::process::ExitCode;
+use std::time::Duration;
+
+#[derive(Parser)]
+#[command(version, about = "Share one rigctld connection between many rigctl clients")]
+struct Args {
+    /// Address downstream clients connect to
+    #[arg(long, default_value = "127.0.0.1:4533")]
+    listen: String,
+
+    /// rigctld host
+    #[arg(long, default_value = "localhost")]
+    host: String,
+
+    /// rigctld port
+    #[arg(long, default_value_t = hamlib_client::builder::DEFAULT_PORT)]
+    port: u16,
+
+    /// Upstream command timeout in milliseconds
+    #[arg(long, default_value_t = 1000)]
+    timeout: u64,
+
+    /// Interval between cache refreshes in milliseconds, 0 to disable polling
+    #[arg(long, default_value_t = 250)]
+    poll_interval: u64,
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let client = RigCtlClient::builder(&args.host)
+        .port(args.port)
+        .command_timeout(Duration::from_millis(args.timeout))
+        .reconnect_policy(ReconnectPolicy::default())
+        .build();
+    let upstream = RigCtlHandle::spawn(client);
+
+    if let Err(e) = upstream.connect().await {
+        eprintln!("Unable to connect to {}:{}: {}", args.host, args.port, e);
+        return ExitCode::FAILURE;
+    }
+
+    let config = ProxyConfig {
+        poll_interval: Duration::from_millis(args.poll_interval),
+        ..Default::default()
+    };
+
+    let proxy = match RigProxy::bind(&args.listen, upstream, config).await {
+        Ok(proxy) => proxy,
+        Err(e) => {
+            eprintln!("Unable to listen on {}: {}", args.listen, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Err(e) = proxy.run().await {
+        eprintln!("Proxy terminated: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
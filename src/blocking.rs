@@ -0,0 +1,470 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use crate::auth::Credentials;
+use crate::cat;
+use crate::cat::CatReply;
+use crate::commands;
+use crate::commands::{password, raw};
+use crate::commands::{get_cache, get_freq, get_info, get_level, get_mode, get_rit, get_split_freq, get_split_mode, get_split_vfo, get_vfo, get_ptt, get_xit, set_cache, set_freq, set_mode, set_ptt, set_trn};
+use crate::error::RigCtlError;
+use crate::mode::Mode;
+use crate::protocol;
+use crate::protocol::{Command, Decoder, ProtocolMode, Response};
+use crate::ptt::PTT;
+use crate::vfo::VFO;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+// Synchronous counterpart of the tokio client. Batches are plain slices of
+// commands; snapshots, watchers, caching, rate limiting and reconnecting
+// need the tokio client
+pub struct RigCtlClient {
+    host: String,
+    port: u16,
+    stream: Option<TcpStream>,
+    decoder: Decoder,
+    timeout: Duration,
+    connect_timeout: Duration,
+    credentials: Option<Credentials>,
+    transceive: bool,
+}
+
+impl RigCtlClient {
+    pub fn new(host: &str, port: u16, timeout: Option<u64>) -> Self {
+        Self {
+            host: String::from(host),
+            port,
+            stream: None,
+            decoder: Decoder::new(ProtocolMode::default()),
+            timeout: Duration::from_millis(timeout.unwrap_or(1000)),
+            connect_timeout: Duration::from_secs(5),
+            credentials: None,
+            transceive: false,
+        }
+    }
+
+    pub fn connect(&mut self) -> Result<(), RigCtlError> {
+        if self.is_connected() {
+            return Err(RigCtlError::AlreadyConnected);
+        }
+
+        let mut last_error = RigCtlError::ConnectionError(format!("Unable to resolve {}:{}", self.host, self.port));
+        for address in (self.host.as_str(), self.port).to_socket_addrs()? {
+            match TcpStream::connect_timeout(&address, self.connect_timeout) {
+                Ok(stream) => {
+                    stream.set_nodelay(true)?;
+                    stream.set_read_timeout(Some(self.timeout))?;
+                    stream.set_write_timeout(Some(self.timeout))?;
+                    self.decoder.clear();
+                    self.stream = Some(stream);
+
+                    if let Err(e) = self.initialize() {
+                        self.stream = None;
+                        return Err(e);
+                    }
+
+                    return Ok(());
+                }
+                Err(e) => last_error = map_io_error(e),
+            }
+        }
+
+        Err(last_error)
+    }
+
+    pub fn disconnect(&mut self) {
+        if !self.is_connected() {
+            return;
+        }
+
+        self.stream = None;
+        self.decoder.clear();
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    pub fn set_communication_timeout(&mut self, timeout: u64) -> Result<(), RigCtlError> {
+        self.timeout = Duration::from_millis(timeout);
+
+        if let Some(stream) = &self.stream {
+            stream.set_read_timeout(Some(self.timeout))?;
+            stream.set_write_timeout(Some(self.timeout))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn protocol_mode(&self) -> ProtocolMode {
+        self.decoder.mode()
+    }
+
+    pub fn set_protocol_mode(&mut self, mode: ProtocolMode) {
+        self.decoder.set_mode(mode);
+    }
+
+    pub fn set_credentials(&mut self, credentials: Option<Credentials>) {
+        self.credentials = credentials;
+    }
+
+    pub fn set_connect_timeout(&mut self, timeout: u64) {
+        self.connect_timeout = Duration::from_millis(timeout);
+    }
+
+    pub fn get_info(&mut self) -> Result<get_info::Response, RigCtlError> {
+        let cmd = Command::GetInfo.to_string();
+        let response = self.execute_command(&cmd)?;
+        get_info::parse(&response)
+    }
+
+    pub fn get_mode(&mut self, vfo: VFO) -> Result<get_mode::Response, RigCtlError> {
+        let cmd = Command::GetMode(vfo).to_string();
+        let response = self.execute_command(&cmd)?;
+        get_mode::parse(&response)
+    }
+
+    pub fn get_freq(&mut self, vfo: VFO) -> Result<get_freq::Response, RigCtlError> {
+        let cmd = Command::GetFreq(vfo).to_string();
+        let response = self.execute_command(&cmd)?;
+        get_freq::parse(&response)
+    }
+
+    pub fn get_vfo(&mut self) -> Result<get_vfo::Response, RigCtlError> {
+        let cmd = Command::GetVfo.to_string();
+        let response = self.execute_command(&cmd)?;
+        get_vfo::parse(&response)
+    }
+
+    pub fn get_split_vfo(&mut self) -> Result<get_split_vfo::Response, RigCtlError> {
+        let cmd = Command::GetSplitVfo.to_string();
+        let response = self.execute_command(&cmd)?;
+        get_split_vfo::parse(&response)
+    }
+
+    pub fn get_split_mode(&mut self, vfo: VFO) -> Result<get_split_mode::Response, RigCtlError> {
+        let cmd = Command::GetSplitMode(vfo).to_string();
+        let response = self.execute_command(&cmd)?;
+        get_split_mode::parse(&response)
+    }
+
+    pub fn get_split_freq(&mut self, vfo: VFO) -> Result<get_split_freq::Response, RigCtlError> {
+        let cmd = Command::GetSplitFreq(vfo).to_string();
+        let response = self.execute_command(&cmd)?;
+        get_split_freq::parse(&response)
+    }
+
+    pub fn set_ptt(&mut self, vfo: VFO, ptt: PTT) -> Result<(), RigCtlError> {
+        let cmd = Command::SetPtt(vfo, ptt).to_string();
+        let response = self.execute_command(&cmd)?;
+        set_ptt::parse(&response)
+    }
+
+    pub fn get_ptt(&mut self, vfo: VFO) -> Result<get_ptt::Response, RigCtlError> {
+        let cmd = Command::GetPtt(vfo).to_string();
+        let response = self.execute_command(&cmd)?;
+        get_ptt::parse(&response)
+    }
+
+    pub fn set_freq(&mut self, vfo: VFO, frequency: u64) -> Result<(), RigCtlError> {
+        let cmd = Command::SetFreq(vfo, frequency).to_string();
+        let response = self.execute_command(&cmd)?;
+        set_freq::parse(&response)
+    }
+
+    pub fn set_mode(&mut self, vfo: VFO, mode: Mode, passband: u64) -> Result<(), RigCtlError> {
+        let cmd = Command::SetMode(vfo, mode, passband).to_string();
+        let response = self.execute_command(&cmd)?;
+        set_mode::parse(&response)
+    }
+
+    pub fn get_level(&mut self, vfo: VFO, level: &'static str) -> Result<get_level::Response, RigCtlError> {
+        let cmd = Command::GetLevel(vfo, level).to_string();
+        let response = self.execute_command(&cmd)?;
+        get_level::parse(&response)
+    }
+
+    pub fn get_rit(&mut self, vfo: VFO) -> Result<get_rit::Response, RigCtlError> {
+        let cmd = Command::GetRit(vfo).to_string();
+        let response = self.execute_command(&cmd)?;
+        get_rit::parse(&response)
+    }
+
+    pub fn get_xit(&mut self, vfo: VFO) -> Result<get_xit::Response, RigCtlError> {
+        let cmd = Command::GetXit(vfo).to_string();
+        let response = self.execute_command(&cmd)?;
+        get_xit::parse(&response)
+    }
+
+    pub fn get_cache(&mut self) -> Result<get_cache::Response, RigCtlError> {
+        let cmd = Command::GetCache.to_string();
+        let response = self.execute_command(&cmd)?;
+        get_cache::parse(&response)
+    }
+
+    pub fn set_cache(&mut self, timeout: u64) -> Result<(), RigCtlError> {
+        let cmd = Command::SetCache(timeout).to_string();
+        let response = self.execute_command(&cmd)?;
+        set_cache::parse(&response)
+    }
+
+    pub fn is_transceive(&self) -> bool {
+        self.transceive
+    }
+
+    // Also sent again on every later connect while enabled
+    pub fn set_transceive(&mut self, enabled: bool) -> Result<(), RigCtlError> {
+        let cmd = Command::SetTrn(enabled).to_string();
+        let response = self.execute_command(&cmd)?;
+        set_trn::parse(&response)?;
+        self.transceive = enabled;
+        Ok(())
+    }
+
+    pub fn execute_raw(&mut self, command: &str, args: &[&str]) -> Result<raw::Response, RigCtlError> {
+        // Raw replies may span any number of lines, which only the extended
+        // newline format frames reliably, whatever the client mode
+        let cmd = raw::compose(command, args)?;
+        match self.execute_commands(ProtocolMode::ExtendedNewline, &[cmd])?.remove(0) {
+            Ok(response) => raw::parse(&response),
+            Err(RigCtlError::HamlibError(code)) => Ok(raw::Response {
+                command: command.to_string(),
+                args: args.join(" "),
+                fields: Vec::new(),
+                code: code.code(),
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn send_cat(&mut self, data: &[u8], reply: Option<CatReply>) -> Result<Vec<u8>, RigCtlError> {
+        let (command, args) = cat::compose(data, reply)?;
+        let args = args.iter().map(String::as_str).collect::<Vec<&str>>();
+        let response = self.execute_raw(command, &args)?;
+        cat::parse_reply(&response)
+    }
+
+    // Sends all the commands at once and then reads the replies, so that the
+    // whole batch costs one round trip
+    pub fn execute_batch(&mut self, commands: &[Command]) -> Result<Vec<Result<Response, RigCtlError>>, RigCtlError> {
+        if commands.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let lines = commands.iter()
+            .map(|command| command.to_string())
+            .collect::<Vec<String>>();
+        let replies = self.execute_commands(self.decoder.mode(), &lines)?;
+
+        Ok(replies.into_iter()
+            .zip(commands)
+            .map(|(reply, command)| reply.and_then(|line| command.parse_response(&line)))
+            .collect())
+    }
+
+    fn initialize(&mut self) -> Result<(), RigCtlError> {
+        if let Some(credentials) = self.credentials.clone() {
+            let command = credentials.command()?;
+            self.write_data(&protocol::encode(self.decoder.mode(), std::slice::from_ref(&command)))?;
+            let line = self.read_line(self.decoder.mode(), &command)?;
+            password::parse(&line)?;
+        }
+
+        if self.transceive {
+            let command = Command::SetTrn(true).to_string();
+            let response = self.execute_command(&command)?;
+            set_trn::parse(&response)?;
+        }
+
+        Ok(())
+    }
+
+    fn execute_command(&mut self, command: &str) -> Result<String, RigCtlError> {
+        self.execute_commands(self.decoder.mode(), &[command.to_string()])?.remove(0)
+    }
+
+    fn execute_commands(&mut self, mode: ProtocolMode, commands: &[String]) -> Result<Vec<Result<String, RigCtlError>>, RigCtlError> {
+        let result = self.exchange(mode, commands);
+
+        // The reply may still arrive and would be taken as the reply to the
+        // next command, so the connection cannot be used anymore
+        if let Err(RigCtlError::CommunicationTimeout) = result {
+            log::debug!("Dropping connection after a timeout");
+            self.disconnect();
+        }
+
+        result
+    }
+
+    fn exchange(&mut self, mode: ProtocolMode, commands: &[String]) -> Result<Vec<Result<String, RigCtlError>>, RigCtlError> {
+        self.write_data(&protocol::encode(mode, commands))?;
+
+        let mut replies = Vec::with_capacity(commands.len());
+        for command in commands {
+            let line = self.read_line(mode, command)?;
+            replies.push(commands::check_return_code(&line).map(|_| line));
+        }
+
+        Ok(replies)
+    }
+
+    fn read_line(&mut self, mode: ProtocolMode, command: &str) -> Result<String, RigCtlError> {
+        log::debug!("Reading line");
+
+        let line = loop {
+            if let Some(line) = self.decoder.next_framed(mode, command)? {
+                break line;
+            }
+
+            let mut buf = [0u8; 4096];
+            let bytes_read = self.stream
+                .as_mut().ok_or(RigCtlError::NotConnected)?
+                .read(&mut buf)
+                .map_err(map_io_error)?;
+
+            if bytes_read == 0 {
+                self.disconnect();
+                return Err(RigCtlError::ConnectionError("Connection closed by remote host".to_string()));
+            }
+
+            self.decoder.feed(&buf[0..bytes_read]);
+        };
+
+        log::trace!(" <<< [{}] ({} bytes)", protocol::redact(&line), line.len());
+
+        Ok(line)
+    }
+
+    fn write_data(&mut self, data: &[u8]) -> Result<(), RigCtlError> {
+        log::debug!("Writing line");
+        log::trace!(" >>> [{}] ({} bytes)", protocol::redact(String::from_utf8_lossy(data).trim_end()), data.len());
+        self.stream
+            .as_mut().ok_or(RigCtlError::NotConnected)?
+            .write_all(data)
+            .map_err(map_io_error)?;
+        Ok(())
+    }
+}
+
+fn map_io_error(error: std::io::Error) -> RigCtlError {
+    match error.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => RigCtlError::CommunicationTimeout,
+        _ => RigCtlError::from(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_blocking_get_freq() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            for line in BufReader::new(stream).lines() {
+                let reply = match line.unwrap().as_str() {
+                    r"|\get_freq VFOA" => "get_freq: VFOA|Frequency: 14074000|RPRT 0",
+                    _ => "RPRT -11",
+                };
+                writer.write_all(format!("{}\n", reply).as_bytes()).unwrap();
+            }
+        });
+
+        let mut client = RigCtlClient::new("127.0.0.1", port, None);
+        client.connect().unwrap();
+
+        let actual = client.get_freq(VFO::VFOA).unwrap();
+        assert_eq!(actual, get_freq::Response { query_vfo: VFO::VFOA, frequency: 14074000u64 });
+        assert!(matches!(client.get_vfo(), Err(RigCtlError::HamlibError(_))));
+    }
+
+    #[test]
+    fn test_blocking_batch_and_raw() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            for line in BufReader::new(stream).lines() {
+                let reply = match line.unwrap().as_str() {
+                    r"|\get_freq VFOA" => "get_freq: VFOA|Frequency: 14074000|RPRT 0\n",
+                    r"|\get_vfo" => "get_vfo: currVFO|VFO: VFOB|RPRT 0\n",
+                    r"+\send_cmd FA;" => "send_cmd: FA;\nReply: FA00014074000;\nRPRT 0\n",
+                    _ => "RPRT -11\n",
+                };
+                writer.write_all(reply.as_bytes()).unwrap();
+            }
+        });
+
+        let mut client = RigCtlClient::new("127.0.0.1", port, None);
+        client.connect().unwrap();
+
+        let actual = client.execute_batch(&[Command::GetFreq(VFO::VFOA), Command::GetVfo]).unwrap();
+        assert_eq!(actual[0].as_ref().unwrap(), &Response::Freq(get_freq::Response { query_vfo: VFO::VFOA, frequency: 14074000u64 }));
+        assert!(matches!(&actual[1], Ok(Response::Vfo(response)) if response.vfo == VFO::VFOB));
+
+        assert_eq!(client.send_cat(b"FA;", None).unwrap(), b"FA00014074000;".to_vec());
+    }
+
+    #[test]
+    fn test_blocking_disconnects_after_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                thread::spawn(move || {
+                    let mut writer = stream.try_clone().unwrap();
+                    for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                        let reply = match line.as_str() {
+                            r"|\get_freq VFOA" => {
+                                thread::sleep(Duration::from_millis(300));
+                                "get_freq: VFOA|Frequency: 14074000|RPRT 0"
+                            }
+                            r"|\get_freq VFOB" => "get_freq: VFOB|Frequency: 7074000|RPRT 0",
+                            _ => "RPRT -11",
+                        };
+                        if writer.write_all(format!("{}\n", reply).as_bytes()).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        let mut client = RigCtlClient::new("127.0.0.1", port, Some(100));
+        client.connect().unwrap();
+
+        assert!(matches!(client.get_freq(VFO::VFOA), Err(RigCtlError::CommunicationTimeout)));
+        assert!(!client.is_connected());
+        assert!(matches!(client.get_freq(VFO::VFOB), Err(RigCtlError::NotConnected)));
+
+        client.connect().unwrap();
+        assert_eq!(client.get_freq(VFO::VFOB).unwrap().frequency, 7074000);
+    }
+}
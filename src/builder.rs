@@ -0,0 +1,180 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use crate::auth::Credentials;
+use crate::cache::CacheConfig;
+use crate::connection::{ReconnectPolicy, TcpOptions};
+use crate::protocol::ProtocolMode;
+use crate::rate_limit::RateLimit;
+use crate::transport::{Connector, Endpoint};
+use crate::RigCtlClient;
+#[cfg(unix)]
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const DEFAULT_PORT: u16 = 4532;
+
+#[derive(Clone)]
+enum Target {
+    Tcp(String),
+    #[cfg(unix)]
+    Unix(std::path::PathBuf),
+    Custom(Arc<dyn Connector>),
+}
+
+#[derive(Clone)]
+pub struct RigCtlClientBuilder {
+    target: Target,
+    port: u16,
+    command_timeout: Duration,
+    deadline: Option<Duration>,
+    protocol_mode: ProtocolMode,
+    credentials: Option<Credentials>,
+    cache: Option<CacheConfig>,
+    rate_limit: Option<RateLimit>,
+    tcp_options: TcpOptions,
+    reconnect_policy: Option<ReconnectPolicy>,
+}
+
+impl RigCtlClientBuilder {
+    pub fn new(host: &str) -> Self {
+        Self::with_target(Target::Tcp(String::from(host)))
+    }
+
+    #[cfg(unix)]
+    pub fn unix<P: AsRef<Path>>(path: P) -> Self {
+        Self::with_target(Target::Unix(path.as_ref().to_path_buf()))
+    }
+
+    pub fn connector<C: Connector + 'static>(connector: C) -> Self {
+        Self::with_target(Target::Custom(Arc::new(connector)))
+    }
+
+    fn with_target(target: Target) -> Self {
+        Self {
+            target,
+            port: DEFAULT_PORT,
+            command_timeout: Duration::from_millis(1000),
+            deadline: None,
+            protocol_mode: ProtocolMode::default(),
+            credentials: None,
+            cache: None,
+            rate_limit: None,
+            tcp_options: TcpOptions::default(),
+            reconnect_policy: None,
+        }
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.tcp_options.connect_timeout = Some(timeout);
+        self
+    }
+
+    pub fn command_timeout(mut self, timeout: Duration) -> Self {
+        self.command_timeout = timeout;
+        self
+    }
+
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    pub fn protocol_mode(mut self, mode: ProtocolMode) -> Self {
+        self.protocol_mode = mode;
+        self
+    }
+
+    pub fn password(mut self, password: &str) -> Self {
+        self.credentials = Some(Credentials::Password(password.to_string()));
+        self
+    }
+
+    pub fn shared_secret(mut self, secret: &str) -> Self {
+        self.credentials = Some(Credentials::SharedSecret(secret.to_string()));
+        self
+    }
+
+    pub fn response_cache(mut self, config: CacheConfig) -> Self {
+        self.cache = Some(config);
+        self
+    }
+
+    pub fn rate_limit(mut self, rate_limit: RateLimit) -> Self {
+        self.rate_limit = Some(rate_limit);
+        self
+    }
+
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.tcp_options.nodelay = nodelay;
+        self
+    }
+
+    pub fn keepalive(mut self, interval: Duration) -> Self {
+        self.tcp_options.keepalive = Some(interval);
+        self
+    }
+
+    pub fn happy_eyeballs_delay(mut self, delay: Duration) -> Self {
+        self.tcp_options.happy_eyeballs_delay = delay;
+        self
+    }
+
+    pub fn reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect_policy = Some(policy);
+        self
+    }
+
+    pub fn build(self) -> RigCtlClient {
+        let endpoint = match self.target {
+            Target::Tcp(host) => Endpoint::Tcp { host, port: self.port, options: self.tcp_options },
+            #[cfg(unix)]
+            Target::Unix(path) => Endpoint::Unix(path),
+            Target::Custom(connector) => Endpoint::Custom(connector),
+        };
+
+        let mut client = RigCtlClient::from_builder(endpoint, self.command_timeout, self.deadline, self.reconnect_policy);
+        client.set_protocol_mode(self.protocol_mode);
+        client.set_credentials(self.credentials);
+        client.set_rate_limit(self.rate_limit);
+        if let Some(config) = self.cache {
+            client.enable_response_cache(config);
+        }
+        client
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_defaults() {
+        let builder = RigCtlClientBuilder::new("localhost");
+        assert_eq!(builder.port, DEFAULT_PORT);
+        assert_eq!(builder.command_timeout, Duration::from_millis(1000));
+        assert_eq!(builder.deadline, None);
+        assert!(builder.tcp_options.nodelay);
+        assert!(builder.tcp_options.connect_timeout.is_some());
+    }
+}
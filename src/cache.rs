@@ -0,0 +1,200 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheConfig {
+    pub default_ttl: Duration,
+    pub ttls: HashMap<String, Duration>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(200))
+    }
+}
+
+impl CacheConfig {
+    pub fn new(default_ttl: Duration) -> Self {
+        Self {
+            default_ttl,
+            ttls: HashMap::new(),
+        }
+    }
+
+    pub fn ttl(mut self, command: &str, ttl: Duration) -> Self {
+        self.ttls.insert(command.to_string(), ttl);
+        self
+    }
+
+    fn ttl_for(&self, name: &str) -> Duration {
+        self.ttls.get(name).copied().unwrap_or(self.default_ttl)
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub invalidations: u64,
+}
+
+// Replies to get_* commands, keyed by the full command line so that the
+// same command on different VFOs gets separate entries
+#[derive(Debug)]
+pub(crate) struct ResponseCache {
+    config: CacheConfig,
+    entries: HashMap<String, (Instant, String)>,
+    stats: CacheStats,
+}
+
+impl ResponseCache {
+    pub(crate) fn new(config: CacheConfig) -> Self {
+        Self {
+            config,
+            entries: HashMap::new(),
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    pub(crate) fn get(&mut self, command: &str) -> Option<String> {
+        let name = command_name(command);
+        if !name.starts_with("get_") {
+            return None;
+        }
+
+        let ttl = self.config.ttl_for(name);
+        match self.entries.get(command) {
+            Some((stored, response)) if stored.elapsed() < ttl => {
+                self.stats.hits += 1;
+                Some(response.clone())
+            }
+            _ => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub(crate) fn update(&mut self, command: &str, response: Option<&str>) {
+        let name = command_name(command);
+
+        if name.starts_with("get_") {
+            if let Some(response) = response {
+                if !self.config.ttl_for(name).is_zero() {
+                    self.entries.insert(command.to_string(), (Instant::now(), response.to_string()));
+                }
+            }
+            return;
+        }
+
+        self.invalidate(name);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn invalidate(&mut self, name: &str) {
+        let before = self.entries.len();
+
+        match name.strip_prefix("set_") {
+            Some("vfo") => self.entries.clear(),
+            Some("split_vfo") => self.entries.retain(|command, _| !command_name(command).starts_with("get_split_")),
+            // With split on, the TX VFO is read by both getters
+            Some("freq" | "split_freq") => self.entries.retain(|command, _| !matches!(command_name(command), "get_freq" | "get_split_freq")),
+            Some("mode" | "split_mode") => self.entries.retain(|command, _| !matches!(command_name(command), "get_mode" | "get_split_mode")),
+            Some(suffix) => {
+                let getter = format!("get_{}", suffix);
+                self.entries.retain(|command, _| command_name(command) != getter);
+            }
+            // Anything the cache cannot reason about (raw CAT frames, vendor
+            // commands) may have changed any state of the rig
+            None => self.entries.clear(),
+        }
+
+        if self.entries.len() != before {
+            self.stats.invalidations += 1;
+        }
+    }
+}
+
+fn command_name(command: &str) -> &str {
+    command.split_whitespace().next().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hit_and_miss() {
+        let mut cache = ResponseCache::new(CacheConfig::new(Duration::from_secs(60)));
+        assert_eq!(cache.get("get_freq VFOA"), None);
+
+        cache.update("get_freq VFOA", Some("get_freq: VFOA|Frequency: 14074000|RPRT 0"));
+        assert!(cache.get("get_freq VFOA").is_some());
+        assert_eq!(cache.get("get_freq VFOB"), None);
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 2, invalidations: 0 });
+    }
+
+    #[test]
+    fn test_expired_entry() {
+        let mut cache = ResponseCache::new(CacheConfig::new(Duration::from_secs(60)).ttl("get_freq", Duration::ZERO));
+        cache.update("get_freq VFOA", Some("get_freq: VFOA|Frequency: 14074000|RPRT 0"));
+        assert_eq!(cache.get("get_freq VFOA"), None);
+    }
+
+    #[test]
+    fn test_invalidation_by_set() {
+        let mut cache = ResponseCache::new(CacheConfig::new(Duration::from_secs(60)));
+        cache.update("get_freq VFOA", Some("get_freq: VFOA|Frequency: 14074000|RPRT 0"));
+        cache.update("get_mode VFOA", Some("get_mode: VFOA|Mode: USB|Passband: 2400|RPRT 0"));
+
+        cache.update("set_freq VFOA 7074000", None);
+        assert_eq!(cache.get("get_freq VFOA"), None);
+        assert!(cache.get("get_mode VFOA").is_some());
+
+        cache.update("set_vfo VFOB", None);
+        assert_eq!(cache.get("get_mode VFOA"), None);
+        assert_eq!(cache.stats().invalidations, 2);
+    }
+
+    #[test]
+    fn test_invalidation_of_split_getters() {
+        let mut cache = ResponseCache::new(CacheConfig::new(Duration::from_secs(60)));
+        cache.update("get_split_freq", Some("get_split_freq: currVFO|TX Frequency: 7074000|RPRT 0"));
+        cache.update("get_split_mode", Some("get_split_mode: currVFO|TX Mode: LSB|TX Passband: 2400|RPRT 0"));
+
+        cache.update("set_freq VFOB 7076000", None);
+        assert_eq!(cache.get("get_split_freq"), None);
+        assert!(cache.get("get_split_mode").is_some());
+
+        cache.update("get_freq VFOB", Some("get_freq: VFOB|Frequency: 7076000|RPRT 0"));
+        cache.update("set_split_freq VFOA 7078000", None);
+        assert_eq!(cache.get("get_freq VFOB"), None);
+
+        cache.update("set_mode VFOB CW 500", None);
+        assert_eq!(cache.get("get_split_mode"), None);
+    }
+}
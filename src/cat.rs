@@ -0,0 +1,124 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use crate::commands::raw;
+use crate::error::{ReturnCode, RigCtlError};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CatReply {
+    Bytes(usize),
+    Terminator(u8),
+}
+
+impl Display for CatReply {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CatReply::Bytes(count) => { write!(f, "{}", count) }
+            CatReply::Terminator(byte) if is_plain(&[*byte]) => { write!(f, "{}", *byte as char) }
+            CatReply::Terminator(byte) => { write!(f, "\\0x{:02X}", byte) }
+        }
+    }
+}
+
+// Printable frames (Kenwood/Yaesu style "FA;") are sent as they are, anything
+// else (Icom CI-V) is escaped byte by byte as rigctld expects: \0xFE\0xFE...
+pub fn encode(data: &[u8]) -> Result<String, RigCtlError> {
+    if data.is_empty() {
+        return Err(RigCtlError::InvalidArgument("Empty CAT command".to_string()));
+    }
+
+    if is_plain(data) {
+        return Ok(String::from_utf8_lossy(data).to_string());
+    }
+
+    Ok(data.iter()
+        .map(|byte| format!("\\0x{:02X}", byte))
+        .collect())
+}
+
+pub fn decode(value: &str) -> Vec<u8> {
+    let tokens = value.split(|c: char| c.is_whitespace() || c == '\\')
+        .filter(|token| !token.is_empty())
+        .collect::<Vec<&str>>();
+
+    let hex = tokens.iter()
+        .map(|token| token.strip_prefix("0x").or(token.strip_prefix("0X")).and_then(|digits| u8::from_str_radix(digits, 16).ok()))
+        .collect::<Option<Vec<u8>>>();
+
+    match hex {
+        Some(bytes) if !bytes.is_empty() => bytes,
+        _ => value.as_bytes().to_vec(),
+    }
+}
+
+pub fn compose(data: &[u8], reply: Option<CatReply>) -> Result<(&'static str, Vec<String>), RigCtlError> {
+    let frame = encode(data)?;
+
+    Ok(match reply {
+        Some(reply) => ("send_cmd_rx", vec![frame, reply.to_string()]),
+        None => ("send_cmd", vec![frame]),
+    })
+}
+
+pub fn parse_reply(response: &raw::Response) -> Result<Vec<u8>, RigCtlError> {
+    if !response.is_ok() {
+        return Err(RigCtlError::HamlibError(ReturnCode::from(response.code)));
+    }
+
+    let value = response.fields.iter()
+        .map(|field| field.value.as_str())
+        .collect::<Vec<&str>>()
+        .join(" ");
+
+    Ok(decode(&value))
+}
+
+fn is_plain(data: &[u8]) -> bool {
+    data.iter().all(|byte| byte.is_ascii_graphic() && *byte != b'\\')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_text() {
+        assert_eq!(encode(b"FA;").unwrap(), "FA;");
+    }
+
+    #[test]
+    fn test_encode_binary() {
+        let frame = [0xFE, 0xFE, 0x94, 0xE0, 0x03, 0xFD];
+        assert_eq!(encode(&frame).unwrap(), r"\0xFE\0xFE\0x94\0xE0\0x03\0xFD");
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode("FA00014074000;"), b"FA00014074000;".to_vec());
+        assert_eq!(decode("0xfe 0xfe 0xe0 0x94 0xfb 0xfd"), vec![0xFE, 0xFE, 0xE0, 0x94, 0xFB, 0xFD]);
+        assert_eq!(decode(r"\0xFE\0xFD"), vec![0xFE, 0xFD]);
+    }
+
+    #[test]
+    fn test_reply_terminator() {
+        assert_eq!(CatReply::Terminator(b';').to_string(), ";");
+        assert_eq!(CatReply::Terminator(0xFD).to_string(), r"\0xFD");
+        assert_eq!(CatReply::Bytes(11).to_string(), "11");
+    }
+}
@@ -0,0 +1,679 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::batch::Batch;
+use crate::cache::{CacheConfig, CacheStats, ResponseCache};
+use crate::cat;
+use crate::cat::CatReply;
+use crate::builder::RigCtlClientBuilder;
+use crate::auth::Credentials;
+use crate::commands;
+use crate::commands::{password, raw};
+use crate::commands::{get_cache, get_freq, get_info, get_level, get_mode, get_rit, get_split_freq, get_split_mode, get_split_vfo, get_vfo, get_ptt, get_xit, set_cache, set_freq, set_mode, set_ptt, set_trn};
+use crate::connection;
+use crate::connection::{ConnectionEvent, ReconnectPolicy};
+use crate::error::RigCtlError;
+use crate::event;
+use crate::event::RigEvent;
+use crate::mode::Mode;
+use crate::protocol;
+use crate::protocol::{Command, Decoder, ProtocolMode};
+use crate::ptt::PTT;
+use crate::rate_limit::{RateLimit, RateLimiter};
+#[cfg(feature = "snapshot")]
+use crate::snapshot;
+#[cfg(feature = "snapshot")]
+use crate::snapshot::RigStateSnapshot;
+use crate::stats::ClientStats;
+#[cfg(feature = "transcript")]
+use crate::transcript::{Direction, TranscriptRecorder};
+use crate::transport::{BoxedTransport, Endpoint, Transport};
+use crate::vfo::VFO;
+#[cfg(unix)]
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::broadcast;
+use tokio::time;
+
+pub struct RigCtlClient {
+    endpoint: Endpoint,
+    stream: Option<BoxedTransport>,
+    decoder: Decoder,
+    timeout: Duration,
+    deadline: Option<Duration>,
+    credentials: Option<Credentials>,
+    cache: Option<ResponseCache>,
+    rate_limiter: Option<RateLimiter>,
+    stats: ClientStats,
+    // Replies read for the commands of the current exchange, so that a
+    // timeout is only charged to the commands still waiting
+    answered: usize,
+    #[cfg(feature = "transcript")]
+    transcript: Option<TranscriptRecorder>,
+    reconnect_policy: Option<ReconnectPolicy>,
+    connection_lost: bool,
+    transceive: bool,
+    pub(crate) events: broadcast::Sender<ConnectionEvent>,
+    pub(crate) rig_events: broadcast::Sender<RigEvent>,
+}
+
+impl RigCtlClient {
+    pub fn new(host: &str, port: u16, timeout: Option<u64>) -> Self {
+        RigCtlClientBuilder::new(host)
+            .port(port)
+            .command_timeout(Duration::from_millis(timeout.unwrap_or(1000)))
+            .build()
+    }
+
+    #[cfg(unix)]
+    pub fn new_unix<P: AsRef<Path>>(path: P, timeout: Option<u64>) -> Self {
+        RigCtlClientBuilder::unix(path)
+            .command_timeout(Duration::from_millis(timeout.unwrap_or(1000)))
+            .build()
+    }
+
+    pub fn from_stream<T: Transport + 'static>(stream: T, timeout: Option<u64>) -> Self {
+        let mut client = Self::from_builder(Endpoint::Stream, Duration::from_millis(timeout.unwrap_or(1000)), None, None);
+        client.stream = Some(Box::new(stream));
+        client
+    }
+
+    pub fn builder(host: &str) -> RigCtlClientBuilder {
+        RigCtlClientBuilder::new(host)
+    }
+
+    pub(crate) fn from_builder(endpoint: Endpoint, timeout: Duration, deadline: Option<Duration>, reconnect_policy: Option<ReconnectPolicy>) -> Self {
+        Self {
+            endpoint,
+            stream: None,
+            decoder: Decoder::new(ProtocolMode::default()),
+            timeout,
+            deadline,
+            credentials: None,
+            cache: None,
+            rate_limiter: None,
+            stats: ClientStats::default(),
+            answered: 0,
+            #[cfg(feature = "transcript")]
+            transcript: None,
+            reconnect_policy,
+            connection_lost: false,
+            transceive: false,
+            events: broadcast::channel(16).0,
+            rig_events: broadcast::channel(64).0,
+        }
+    }
+
+    pub async fn connect(&mut self) -> Result<(), RigCtlError> {
+        if self.is_connected() {
+            return Err(RigCtlError::AlreadyConnected);
+        }
+
+        self.open_stream().await?;
+        self.connection_lost = false;
+        self.emit(ConnectionEvent::Connected);
+
+        Ok(())
+    }
+
+    pub fn disconnect(&mut self) {
+        self.connection_lost = false;
+
+        if !self.is_connected() {
+            return;
+        }
+
+        self.stream = None;
+        self.emit(ConnectionEvent::Disconnected);
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    pub fn set_communication_timeout(&mut self, timeout: u64) {
+        self.timeout = Duration::from_millis(timeout);
+    }
+
+    pub fn protocol_mode(&self) -> ProtocolMode {
+        self.decoder.mode()
+    }
+
+    pub fn set_protocol_mode(&mut self, mode: ProtocolMode) {
+        self.decoder.set_mode(mode);
+    }
+
+    pub fn set_credentials(&mut self, credentials: Option<Credentials>) {
+        self.credentials = credentials;
+    }
+
+    pub fn enable_response_cache(&mut self, config: CacheConfig) {
+        self.cache = Some(ResponseCache::new(config));
+    }
+
+    pub fn disable_response_cache(&mut self) {
+        self.cache = None;
+    }
+
+    pub fn clear_response_cache(&mut self) {
+        if let Some(cache) = &mut self.cache {
+            cache.clear();
+        }
+    }
+
+    pub fn response_cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(|cache| cache.stats())
+    }
+
+    pub fn stats(&self) -> ClientStats {
+        self.stats.clone()
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = ClientStats::default();
+    }
+
+    #[cfg(feature = "transcript")]
+    pub fn set_transcript(&mut self, recorder: Option<TranscriptRecorder>) {
+        self.transcript = recorder;
+    }
+
+    pub fn set_rate_limit(&mut self, rate_limit: Option<RateLimit>) {
+        self.rate_limiter = rate_limit.map(RateLimiter::new);
+    }
+
+    pub fn set_reconnect_policy(&mut self, policy: Option<ReconnectPolicy>) {
+        self.reconnect_policy = policy;
+    }
+
+    pub fn subscribe_connection_events(&self) -> broadcast::Receiver<ConnectionEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn subscribe_rig_events(&self) -> broadcast::Receiver<RigEvent> {
+        self.rig_events.subscribe()
+    }
+
+    pub fn is_transceive(&self) -> bool {
+        self.transceive
+    }
+
+    // With transceive on, the rig pushes its changes as unsolicited lines on
+    // the connection; they are set aside while replies are read, published
+    // to subscribers, and next_event waits for them while no command runs.
+    // Hamlib 4 deprecates the command and many builds answer ENIMPL, or only
+    // publish the changes through multicast (see `multicast`)
+    pub async fn set_transceive(&mut self, enabled: bool) -> Result<(), RigCtlError> {
+        let cmd = Command::SetTrn(enabled).to_string();
+        let response = self.execute_command(&cmd).await?;
+        set_trn::parse(&response)?;
+        self.transceive = enabled;
+        Ok(())
+    }
+
+    // Cancel-safe: an event read before the future is dropped is still
+    // published to subscribers
+    pub async fn next_event(&mut self) -> Result<RigEvent, RigCtlError> {
+        loop {
+            while let Some(line) = self.decoder.next_event() {
+                if let Some(event) = self.publish_event(&line) {
+                    return Ok(event);
+                }
+            }
+
+            if let Err(e) = self.fill().await {
+                if let RigCtlError::ConnectionError(_) = e {
+                    self.stream = None;
+                    self.connection_lost = true;
+                    self.emit(ConnectionEvent::Disconnected);
+                    let _ = self.rig_events.send(RigEvent::Disconnected);
+                }
+                return Err(e);
+            }
+        }
+    }
+
+    pub fn batch(&mut self) -> Batch<'_> {
+        Batch::new(self)
+    }
+
+    pub async fn get_info(&mut self) -> Result<get_info::Response, RigCtlError> {
+        let cmd = Command::GetInfo.to_string();
+        let response = self.execute_command(&cmd).await?;
+        get_info::parse(&response)
+    }
+
+    pub async fn get_mode(&mut self, vfo: VFO) -> Result<get_mode::Response, RigCtlError> {
+        let cmd = Command::GetMode(vfo).to_string();
+        let response = self.execute_command(&cmd).await?;
+        get_mode::parse(&response)
+    }
+
+    pub async fn get_freq(&mut self, vfo: VFO) -> Result<get_freq::Response, RigCtlError> {
+        let cmd = Command::GetFreq(vfo).to_string();
+        let response = self.execute_command(&cmd).await?;
+        get_freq::parse(&response)
+    }
+
+    pub async fn get_vfo(&mut self) -> Result<get_vfo::Response, RigCtlError> {
+        let cmd = Command::GetVfo.to_string();
+        let response = self.execute_command(&cmd).await?;
+        get_vfo::parse(&response)
+    }
+
+    pub async fn get_split_vfo(&mut self) -> Result<get_split_vfo::Response, RigCtlError> {
+        let cmd = Command::GetSplitVfo.to_string();
+        let response = self.execute_command(&cmd).await?;
+        get_split_vfo::parse(&response)
+    }
+
+    pub async fn get_split_mode(&mut self, vfo: VFO) -> Result<get_split_mode::Response, RigCtlError> {
+        let cmd = Command::GetSplitMode(vfo).to_string();
+        let response = self.execute_command(&cmd).await?;
+        get_split_mode::parse(&response)
+    }
+
+    pub async fn get_split_freq(&mut self, vfo: VFO) -> Result<get_split_freq::Response, RigCtlError> {
+        let cmd = Command::GetSplitFreq(vfo).to_string();
+        let response = self.execute_command(&cmd).await?;
+        get_split_freq::parse(&response)
+    }
+
+    pub async fn set_ptt(&mut self, vfo: VFO, ptt: PTT) -> Result<(), RigCtlError> {
+        let cmd = Command::SetPtt(vfo, ptt).to_string();
+        let response = self.execute_command(&cmd).await?;
+        set_ptt::parse(&response)
+    }
+
+    pub async fn get_ptt(&mut self, vfo: VFO) -> Result<get_ptt::Response, RigCtlError> {
+        let cmd = Command::GetPtt(vfo).to_string();
+        let response = self.execute_command(&cmd).await?;
+        get_ptt::parse(&response)
+    }
+
+    pub async fn set_freq(&mut self, vfo: VFO, frequency: u64) -> Result<(), RigCtlError> {
+        let cmd = Command::SetFreq(vfo, frequency).to_string();
+        let response = self.execute_command(&cmd).await?;
+        set_freq::parse(&response)
+    }
+
+    pub async fn set_mode(&mut self, vfo: VFO, mode: Mode, passband: u64) -> Result<(), RigCtlError> {
+        let cmd = Command::SetMode(vfo, mode, passband).to_string();
+        let response = self.execute_command(&cmd).await?;
+        set_mode::parse(&response)
+    }
+
+    pub async fn get_level(&mut self, vfo: VFO, level: &'static str) -> Result<get_level::Response, RigCtlError> {
+        let cmd = Command::GetLevel(vfo, level).to_string();
+        let response = self.execute_command(&cmd).await?;
+        get_level::parse(&response)
+    }
+
+    pub async fn get_rit(&mut self, vfo: VFO) -> Result<get_rit::Response, RigCtlError> {
+        let cmd = Command::GetRit(vfo).to_string();
+        let response = self.execute_command(&cmd).await?;
+        get_rit::parse(&response)
+    }
+
+    pub async fn get_xit(&mut self, vfo: VFO) -> Result<get_xit::Response, RigCtlError> {
+        let cmd = Command::GetXit(vfo).to_string();
+        let response = self.execute_command(&cmd).await?;
+        get_xit::parse(&response)
+    }
+
+    #[cfg(feature = "snapshot")]
+    pub async fn snapshot(&mut self) -> Result<RigStateSnapshot, RigCtlError> {
+        snapshot::take(self).await
+    }
+
+    pub async fn get_cache(&mut self) -> Result<get_cache::Response, RigCtlError> {
+        let cmd = Command::GetCache.to_string();
+        let response = self.execute_command(&cmd).await?;
+        get_cache::parse(&response)
+    }
+
+    pub async fn set_cache(&mut self, timeout: u64) -> Result<(), RigCtlError> {
+        let cmd = Command::SetCache(timeout).to_string();
+        let response = self.execute_command(&cmd).await?;
+        set_cache::parse(&response)
+    }
+
+    pub async fn execute_raw(&mut self, command: &str, args: &[&str]) -> Result<raw::Response, RigCtlError> {
+        // Raw replies may span any number of lines, which only the extended
+        // newline format frames reliably, whatever the client mode
+        let cmd = raw::compose(command, args)?;
+        match self.execute_commands_in(ProtocolMode::ExtendedNewline, &[cmd]).await?.remove(0) {
+            Ok(response) => raw::parse(&response),
+            Err(RigCtlError::HamlibError(code)) => Ok(raw::Response {
+                command: command.to_string(),
+                args: args.join(" "),
+                fields: Vec::new(),
+                code: code.code(),
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn send_cat(&mut self, data: &[u8], reply: Option<CatReply>) -> Result<Vec<u8>, RigCtlError> {
+        let (command, args) = cat::compose(data, reply)?;
+        let args = args.iter().map(String::as_str).collect::<Vec<&str>>();
+        let response = self.execute_raw(command, &args).await?;
+        cat::parse_reply(&response)
+    }
+
+    pub(crate) async fn execute_command(&mut self, command: &str) -> Result<String, RigCtlError> {
+        self.execute_commands(&[command.to_string()]).await?.remove(0)
+    }
+
+    pub(crate) async fn execute_commands(&mut self, commands: &[String]) -> Result<Vec<Result<String, RigCtlError>>, RigCtlError> {
+        self.execute_commands_in(self.decoder.mode(), commands).await
+    }
+
+    async fn execute_commands_in(&mut self, mode: ProtocolMode, commands: &[String]) -> Result<Vec<Result<String, RigCtlError>>, RigCtlError> {
+        let cache = match &mut self.cache {
+            Some(cache) => cache,
+            None => return self.execute_uncached(mode, commands).await,
+        };
+
+        // Once a command may change the rig state, later reads in the same
+        // batch must go to the rig
+        let mut bypass = false;
+        let mut cached = Vec::with_capacity(commands.len());
+        for command in commands {
+            bypass |= !command.starts_with("get_");
+            cached.push(if bypass { None } else { cache.get(command) });
+        }
+
+        let pending = commands.iter()
+            .zip(&cached)
+            .filter(|(_, reply)| reply.is_none())
+            .map(|(command, _)| command.clone())
+            .collect::<Vec<String>>();
+
+        let mut fresh = match pending.is_empty() {
+            true => Vec::new(),
+            false => self.execute_uncached(mode, &pending).await?,
+        }.into_iter();
+
+        let mut replies = Vec::with_capacity(commands.len());
+        for (command, reply) in commands.iter().zip(cached) {
+            let reply = match reply {
+                Some(line) => Ok(line),
+                None => {
+                    let reply = fresh.next()
+                        .unwrap_or_else(|| Err(RigCtlError::ResponseParsing("Missing reply".to_string())));
+                    if let Some(cache) = &mut self.cache {
+                        cache.update(command, reply.as_ref().ok().map(String::as_str));
+                    }
+                    reply
+                }
+            };
+            replies.push(reply);
+        }
+
+        Ok(replies)
+    }
+
+    async fn execute_uncached(&mut self, mode: ProtocolMode, commands: &[String]) -> Result<Vec<Result<String, RigCtlError>>, RigCtlError> {
+        let result = match self.deadline {
+            Some(deadline) => time::timeout(deadline, self.run_commands(mode, commands))
+                .await
+                .map_err(|_| RigCtlError::CommunicationTimeout)
+                .and_then(|result| result),
+            None => self.run_commands(mode, commands).await,
+        };
+
+        if let Err(RigCtlError::CommunicationTimeout) = result {
+            for command in commands.iter().skip(self.answered) {
+                self.stats.record_timeout(command);
+            }
+
+            // The reply may still arrive and would be taken as the reply to
+            // the next command, so the connection cannot be used anymore
+            if self.is_connected() {
+                log::debug!("Dropping connection after a timeout");
+                self.stream = None;
+                self.connection_lost = true;
+                self.emit(ConnectionEvent::Disconnected);
+            }
+        }
+
+        // Events pushed while the replies were read
+        while let Some(line) = self.decoder.next_event() {
+            self.publish_event(&line);
+        }
+
+        result
+    }
+
+    async fn run_commands(&mut self, mode: ProtocolMode, commands: &[String]) -> Result<Vec<Result<String, RigCtlError>>, RigCtlError> {
+        if !self.is_connected() && self.connection_lost && self.reconnect_policy.is_some() {
+            self.reconnect().await?;
+        }
+
+        match self.exchange(mode, commands).await {
+            Err(RigCtlError::ConnectionError(message)) => {
+                self.stream = None;
+                self.emit(ConnectionEvent::Disconnected);
+
+                if self.reconnect_policy.is_none() {
+                    return Err(RigCtlError::ConnectionError(message));
+                }
+
+                self.connection_lost = true;
+                self.reconnect().await?;
+
+                if !commands.iter().all(|command| connection::is_replayable(command)) {
+                    log::warn!("Not replaying commands that are not read-only after reconnect: {:?}", commands);
+                    return Err(RigCtlError::ConnectionError(message));
+                }
+
+                log::debug!("Replaying commands after reconnect: {:?}", commands);
+                self.exchange(mode, commands).await
+            }
+            result => result,
+        }
+    }
+
+    async fn exchange(&mut self, mode: ProtocolMode, commands: &[String]) -> Result<Vec<Result<String, RigCtlError>>, RigCtlError> {
+        let mut replies = Vec::with_capacity(commands.len());
+        self.answered = 0;
+
+        // A throttled command is only sent once the rig has answered the
+        // previous one, so that the limiter spaces what the rig processes
+        // rather than what sits in its input buffer
+        if self.rate_limiter.is_some() {
+            for command in commands {
+                self.throttle(command).await;
+                let sent = Instant::now();
+                self.write_data(&protocol::encode(mode, std::slice::from_ref(command))).await?;
+                replies.push(self.read_reply(mode, command, sent).await?);
+            }
+            return Ok(replies);
+        }
+
+        // Pipelined commands are timed from the previous reply, as the rig
+        // only starts on a command once it has answered the one before
+        let mut since = Instant::now();
+        self.write_data(&protocol::encode(mode, commands)).await?;
+        for command in commands {
+            replies.push(self.read_reply(mode, command, since).await?);
+            since = Instant::now();
+        }
+
+        Ok(replies)
+    }
+
+    async fn read_reply(&mut self, mode: ProtocolMode, command: &str, sent: Instant) -> Result<Result<String, RigCtlError>, RigCtlError> {
+        let line = self.read_framed(mode, command).await?;
+        let reply = commands::check_return_code(&line).map(|_| line);
+        self.stats.record_reply(command, sent.elapsed(), &reply);
+        self.answered += 1;
+        Ok(reply)
+    }
+
+    async fn throttle(&mut self, command: &str) {
+        let delay = match &mut self.rate_limiter {
+            Some(limiter) => limiter.reserve(command, Instant::now()),
+            None => return,
+        };
+
+        if !delay.is_zero() {
+            log::trace!("Delaying command by {} ms", delay.as_millis());
+            time::sleep(delay).await;
+        }
+    }
+
+    async fn open_stream(&mut self) -> Result<(), RigCtlError> {
+        let stream = self.endpoint.open().await?;
+        self.stream = Some(stream);
+        self.decoder.clear();
+        self.clear_response_cache();
+
+        if let Some(credentials) = self.credentials.clone() {
+            if let Err(e) = self.authenticate(&credentials).await {
+                self.stream = None;
+                return Err(e);
+            }
+        }
+
+        if self.transceive {
+            let command = Command::SetTrn(true).to_string();
+            self.write_data(&protocol::encode(self.decoder.mode(), std::slice::from_ref(&command))).await?;
+            let line = self.read_line(&command).await?;
+            set_trn::parse(&line)?;
+        }
+
+        Ok(())
+    }
+
+    async fn authenticate(&mut self, credentials: &Credentials) -> Result<(), RigCtlError> {
+        log::debug!("Authenticating to {}", self.endpoint);
+        let command = credentials.command()?;
+        self.write_data(&protocol::encode(self.decoder.mode(), std::slice::from_ref(&command))).await?;
+        let line = self.read_line(&command).await?;
+        password::parse(&line)
+    }
+
+    async fn reconnect(&mut self) -> Result<(), RigCtlError> {
+        let policy = self.reconnect_policy.clone().unwrap_or_default();
+
+        for attempt in 1..=policy.max_attempts {
+            let delay = policy.backoff(attempt);
+            self.emit(ConnectionEvent::Reconnecting { attempt, delay });
+            time::sleep(delay).await;
+
+            match self.open_stream().await {
+                Ok(()) => {
+                    log::info!("Reconnected to {} after {} attempts", self.endpoint, attempt);
+                    self.connection_lost = false;
+                    self.emit(ConnectionEvent::Reconnected { attempts: attempt });
+                    return Ok(());
+                }
+                Err(e @ (RigCtlError::AuthenticationFailed | RigCtlError::AuthenticationUnsupported(_))) => {
+                    log::warn!("Authentication failed while reconnecting to {}", self.endpoint);
+                    self.emit(ConnectionEvent::ReconnectFailed { attempts: attempt });
+                    return Err(e);
+                }
+                Err(e) => log::debug!("Reconnect attempt {} failed: {}", attempt, e),
+            }
+        }
+
+        self.emit(ConnectionEvent::ReconnectFailed { attempts: policy.max_attempts });
+        Err(RigCtlError::ConnectionError(format!("Unable to reconnect after {} attempts", policy.max_attempts)))
+    }
+
+    fn emit(&self, event: ConnectionEvent) {
+        log::debug!("Connection event: {}", event);
+        let _ = self.events.send(event);
+    }
+
+    async fn read_line(&mut self, command: &str) -> Result<String, RigCtlError> {
+        self.read_framed(self.decoder.mode(), command).await
+    }
+
+    async fn read_framed(&mut self, mode: ProtocolMode, command: &str) -> Result<String, RigCtlError> {
+        log::debug!("Reading line");
+
+        let line = time::timeout(self.timeout, self.next_line(mode, command))
+            .await
+            .map_err(|_| RigCtlError::CommunicationTimeout)??;
+
+        log::trace!(" <<< [{}] ({} bytes)", protocol::redact(&line), line.len());
+
+        Ok(line)
+    }
+
+    async fn next_line(&mut self, mode: ProtocolMode, command: &str) -> Result<String, RigCtlError> {
+        loop {
+            if let Some(line) = self.decoder.next_framed(mode, command)? {
+                return Ok(line);
+            }
+
+            self.fill().await?;
+        }
+    }
+
+    // Reads whatever is available into the decoder; cancel-safe, nothing is
+    // lost if the future is dropped while waiting
+    async fn fill(&mut self) -> Result<(), RigCtlError> {
+        let mut buf = [0u8; 4096];
+        let bytes_read = self.stream
+            .as_mut().ok_or(RigCtlError::NotConnected)?
+            .read(&mut buf)
+            .await?;
+
+        if bytes_read == 0 {
+            return Err(RigCtlError::ConnectionError("Connection closed by remote host".to_string()));
+        }
+
+        #[cfg(feature = "transcript")]
+        if let Some(transcript) = &mut self.transcript {
+            transcript.record(Direction::Received, &buf[0..bytes_read]);
+        }
+
+        self.decoder.feed(&buf[0..bytes_read]);
+
+        Ok(())
+    }
+
+    // Pushed changes make cached replies stale
+    fn publish_event(&mut self, line: &str) -> Option<RigEvent> {
+        let event = event::parse(line)?;
+        log::debug!("Rig event: {}", event);
+        self.clear_response_cache();
+        let _ = self.rig_events.send(event.clone());
+        Some(event)
+    }
+
+    async fn write_data(&mut self, data: &[u8]) -> Result<(), RigCtlError> {
+        log::debug!("Writing line");
+        log::trace!(" >>> [{}] ({} bytes)", protocol::redact(String::from_utf8_lossy(data).trim_end()), data.len());
+        #[cfg(feature = "transcript")]
+        if let Some(transcript) = &mut self.transcript {
+            transcript.record(Direction::Sent, data);
+        }
+        time::timeout(
+            self.timeout,
+            self.stream
+                .as_mut().ok_or(RigCtlError::NotConnected)?
+                .write_all(data))
+            .await
+            .map_err(|_| RigCtlError::CommunicationTimeout)??;
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use crate::error::RigCtlError;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    pub timeout: u64,
+}
+
+impl Display for Response {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Cache timeout: {} ms", self.timeout)
+    }
+}
+
+pub fn parse(line: &str) -> Result<Response, RigCtlError> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"^get_cache: [^|]*\|(?:[^|:]+: )?(?P<timeout>[0-9]+)\|RPRT 0$").unwrap();
+    }
+
+    let captures = RE.captures(line).ok_or(RigCtlError::ResponseParsing("Unable to capture groups".to_string()))?;
+    log::trace!("Captures: {:?}", &captures);
+
+    let timeout = captures.name("timeout").ok_or(RigCtlError::ResponseParsing("Invalid timeout group".to_string()))?.as_str();
+
+    Ok(Response {
+        timeout: timeout.parse::<u64>().map_err(|e| { RigCtlError::ResponseParsing(e.to_string()) })?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_cache() {
+        let input = r"get_cache: |Timeout (ms): 500|RPRT 0";
+        let expected = Response { timeout: 500u64 };
+        let actual = parse(input);
+        assert!(actual.is_ok());
+        assert_eq!(actual.unwrap(), expected);
+    }
+}
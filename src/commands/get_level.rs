@@ -0,0 +1,78 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use crate::error::RigCtlError;
+use crate::vfo::VFO;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    pub query_vfo: VFO,
+    pub level: String,
+    pub value: f64,
+}
+
+impl Display for Response {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Query VFO: {} - Level: {} - Value: {}", self.query_vfo, self.level, self.value)
+    }
+}
+
+pub fn parse(line: &str) -> Result<Response, RigCtlError> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"^get_level: (?P<query_vfo>[a-zA-Z0-9]+) (?P<level>[A-Z0-9_]+)\|Level Value: (?P<value>-?[0-9]+(?:\.[0-9]+)?)\|RPRT 0$").unwrap();
+    }
+
+    let captures = RE.captures(line).ok_or(RigCtlError::ResponseParsing("Unable to capture groups".to_string()))?;
+    log::trace!("Captures: {:?}", &captures);
+
+    let query_vfo = captures.name("query_vfo").ok_or(RigCtlError::ResponseParsing("Invalid query_vfo group".to_string()))?.as_str();
+    let level = captures.name("level").ok_or(RigCtlError::ResponseParsing("Invalid level group".to_string()))?.as_str();
+    let value = captures.name("value").ok_or(RigCtlError::ResponseParsing("Invalid value group".to_string()))?.as_str();
+
+    Ok(Response {
+        query_vfo: VFO::from_str(query_vfo)?,
+        level: level.to_string(),
+        value: value.parse::<f64>().map_err(|e| RigCtlError::ResponseParsing(e.to_string()))?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_level() {
+        let input = r"get_level: VFOA STRENGTH|Level Value: -54|RPRT 0";
+        let expected = Response { query_vfo: VFO::VFOA, level: "STRENGTH".to_string(), value: -54f64 };
+        let actual = parse(input);
+        assert!(actual.is_ok());
+        assert_eq!(actual.unwrap(), expected);
+    }
+
+    #[test]
+    fn test_get_level_fractional() {
+        let input = r"get_level: VFOA RFPOWER|Level Value: 0.500000|RPRT 0";
+        let expected = Response { query_vfo: VFO::VFOA, level: "RFPOWER".to_string(), value: 0.5f64 };
+        let actual = parse(input);
+        assert!(actual.is_ok());
+        assert_eq!(actual.unwrap(), expected);
+    }
+}
@@ -40,7 +40,7 @@ pub fn parse(line: &str) -> Result<Response, RigCtlError> {
         static ref RE: Regex = Regex::new(r"^get_mode: (?P<query_vfo>[a-zA-Z0-9]+)\|Mode: (?P<mode>[a-zA-Z0-9]+)\|Passband: (?P<passband>[0-9]+)\|RPRT 0$").unwrap();
     }
 
-    let captures = RE.captures(&line).ok_or(RigCtlError::ResponseParsing("Unable to capture groups".to_string()))?;
+    let captures = RE.captures(line).ok_or(RigCtlError::ResponseParsing("Unable to capture groups".to_string()))?;
     log::trace!("Captures: {:?}", &captures);
 
     let query_vfo = captures.name("query_vfo").ok_or(RigCtlError::ResponseParsing("Invalid query_vfo group".to_string()))?.as_str();
@@ -48,8 +48,8 @@ pub fn parse(line: &str) -> Result<Response, RigCtlError> {
     let passband = captures.name("passband").ok_or(RigCtlError::ResponseParsing("Invalid passband group".to_string()))?.as_str();
 
     Ok(Response {
-        query_vfo: VFO::from_str(&query_vfo)?,
-        mode: Mode::from_str(&mode)?,
+        query_vfo: VFO::from_str(query_vfo)?,
+        mode: Mode::from_str(mode)?,
         passband: passband.parse::<u64>().map_err(|e| { RigCtlError::ResponseParsing(e.to_string()) })?,
     })
 }
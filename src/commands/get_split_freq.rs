@@ -38,14 +38,14 @@ pub fn parse(line: &str) -> Result<Response, RigCtlError> {
         static ref RE: Regex = Regex::new(r"^get_split_freq: (?P<query_vfo>[a-zA-Z0-9]+)\|TX Frequency: (?P<frequency>[0-9]+)\|RPRT 0$").unwrap();
     }
 
-    let captures = RE.captures(&line).ok_or(RigCtlError::ResponseParsing("Unable to capture groups".to_string()))?;
+    let captures = RE.captures(line).ok_or(RigCtlError::ResponseParsing("Unable to capture groups".to_string()))?;
     log::trace!("Captures: {:?}", &captures);
 
     let query_vfo = captures.name("query_vfo").ok_or(RigCtlError::ResponseParsing("Invalid query_vfo group".to_string()))?.as_str();
     let frequency = captures.name("frequency").ok_or(RigCtlError::ResponseParsing("Invalid frequency group".to_string()))?.as_str();
 
     Ok(Response {
-        query_vfo: VFO::from_str(&query_vfo)?,
+        query_vfo: VFO::from_str(query_vfo)?,
         frequency: frequency.parse::<u64>().map_err(|e| { RigCtlError::ResponseParsing(e.to_string()) })?,
     })
 }
@@ -39,7 +39,7 @@ pub fn parse(line: &str) -> Result<Response, RigCtlError> {
         static ref RE: Regex = Regex::new(r"^get_split_vfo: (?P<query_vfo>[a-zA-Z0-9]+)\|Split: (?P<split>[01])\|TX VFO: (?P<tx_vfo>[a-zA-Z0-9]+)\|RPRT 0$").unwrap();
     }
 
-    let captures = RE.captures(&line).ok_or(RigCtlError::ResponseParsing("Unable to capture groups".to_string()))?;
+    let captures = RE.captures(line).ok_or(RigCtlError::ResponseParsing("Unable to capture groups".to_string()))?;
     log::trace!("Captures: {:?}", &captures);
 
     let query_vfo = captures.name("query_vfo").ok_or(RigCtlError::ResponseParsing("Invalid query_vfo group".to_string()))?.as_str();
@@ -47,9 +47,9 @@ pub fn parse(line: &str) -> Result<Response, RigCtlError> {
     let tx_vfo = captures.name("tx_vfo").ok_or(RigCtlError::ResponseParsing("Invalid vfo group".to_string()))?.as_str();
 
     Ok(Response {
-        query_vfo: VFO::from_str(&query_vfo)?,
+        query_vfo: VFO::from_str(query_vfo)?,
         split: split == "1",
-        tx_vfo: VFO::from_str(&tx_vfo)?,
+        tx_vfo: VFO::from_str(tx_vfo)?,
     })
 }
 
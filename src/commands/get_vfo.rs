@@ -38,15 +38,15 @@ pub fn parse(line: &str) -> Result<Response, RigCtlError> {
         static ref RE: Regex = Regex::new(r"^get_vfo: (?P<query_vfo>[a-zA-Z0-9]+)\|VFO: (?P<vfo>[a-zA-Z0-9]+)\|RPRT 0$").unwrap();
     }
 
-    let captures = RE.captures(&line).ok_or(RigCtlError::ResponseParsing("Unable to capture groups".to_string()))?;
+    let captures = RE.captures(line).ok_or(RigCtlError::ResponseParsing("Unable to capture groups".to_string()))?;
     log::trace!("Captures: {:?}", &captures);
 
     let query_vfo = captures.name("query_vfo").ok_or(RigCtlError::ResponseParsing("Invalid query_vfo group".to_string()))?.as_str();
     let vfo = captures.name("vfo").ok_or(RigCtlError::ResponseParsing("Invalid vfo group".to_string()))?.as_str();
 
     Ok(Response {
-        query_vfo: VFO::from_str(&query_vfo)?,
-        vfo: VFO::from_str(&vfo)?,
+        query_vfo: VFO::from_str(query_vfo)?,
+        vfo: VFO::from_str(vfo)?,
     })
 }
 
@@ -14,10 +14,61 @@
  *
  */
 
-pub(crate) mod get_info;
-pub(crate) mod get_mode;
-pub(crate) mod get_freq;
-pub(crate) mod get_vfo;
-pub(crate) mod get_split_vfo;
-pub(crate) mod get_split_mode;
-pub(crate) mod get_split_freq;
\ No newline at end of file
+
+use crate::error::{ReturnCode, RigCtlError};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+pub mod get_info;
+pub mod get_mode;
+pub mod get_freq;
+pub mod get_vfo;
+pub mod get_split_vfo;
+pub mod get_split_mode;
+pub mod get_split_freq;
+pub mod set_ptt;
+pub mod get_ptt;
+pub mod set_freq;
+pub mod set_mode;
+pub mod get_level;
+pub mod get_rit;
+pub mod get_xit;
+pub mod get_cache;
+pub mod set_cache;
+pub mod set_trn;
+pub mod password;
+pub mod raw;
+
+pub fn check_return_code(line: &str) -> Result<(), RigCtlError> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"RPRT (?P<code>-?[0-9]+)$").unwrap();
+    }
+
+    let code = match RE.captures(line).and_then(|captures| captures.name("code")) {
+        Some(code) => code.as_str().parse::<i32>().map_err(|e| { RigCtlError::ResponseParsing(e.to_string()) })?,
+        None => return Ok(()),
+    };
+
+    match code {
+        0 => Ok(()),
+        _ => Err(RigCtlError::HamlibError(ReturnCode::from(code))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_return_code_ok() {
+        let input = r"get_freq: None|Frequency: 14074000|RPRT 0";
+        assert!(check_return_code(input).is_ok());
+    }
+
+    #[test]
+    fn test_check_return_code_error() {
+        let input = r"get_freq: VFOA|RPRT -11";
+        let actual = check_return_code(input);
+        assert!(matches!(actual, Err(RigCtlError::HamlibError(ReturnCode::ENAVAIL))));
+    }
+}
@@ -0,0 +1,71 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use crate::commands;
+use crate::error::{ReturnCode, RigCtlError};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+pub fn parse(line: &str) -> Result<(), RigCtlError> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"^password: [^|]*\|RPRT 0$").unwrap();
+    }
+
+    // rigctld answers a wrong password with EPROTO, or ESECURITY on builds
+    // that report it; anything else, such as ENIMPL from a build without
+    // authentication, is a plain Hamlib error
+    match commands::check_return_code(line) {
+        Ok(()) => {}
+        Err(RigCtlError::HamlibError(code @ (ReturnCode::EPROTO | ReturnCode::ESECURITY))) => {
+            log::debug!("Authentication rejected: {}", code);
+            return Err(RigCtlError::AuthenticationFailed);
+        }
+        Err(e) => return Err(e),
+    }
+
+    if !RE.is_match(line) {
+        return Err(RigCtlError::ResponseParsing("Unable to match response".to_string()));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_password() {
+        let input = r"password: secret|RPRT 0";
+        assert!(parse(input).is_ok());
+    }
+
+    #[test]
+    fn test_password_rejected() {
+        let input = r"password: wrong|RPRT -8";
+        assert!(matches!(parse(input), Err(RigCtlError::AuthenticationFailed)));
+
+        let input = r"password: wrong|RPRT -19";
+        assert!(matches!(parse(input), Err(RigCtlError::AuthenticationFailed)));
+    }
+
+    #[test]
+    fn test_password_not_implemented() {
+        let input = r"password: secret|RPRT -4";
+        assert!(matches!(parse(input), Err(RigCtlError::HamlibError(ReturnCode::ENIMPL))));
+    }
+}
@@ -0,0 +1,127 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use crate::error::RigCtlError;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub command: String,
+    pub args: String,
+    pub fields: Vec<Field>,
+    pub code: i32,
+}
+
+impl Response {
+    pub fn is_ok(&self) -> bool {
+        self.code == 0
+    }
+
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields.iter()
+            .find(|field| field.name == name)
+            .map(|field| field.value.as_str())
+    }
+}
+
+impl Display for Response {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Command: {} - Fields: {} - RPRT: {}", self.command, self.fields.len(), self.code)
+    }
+}
+
+pub fn compose(command: &str, args: &[&str]) -> Result<String, RigCtlError> {
+    if command.is_empty() || command.contains(char::is_whitespace) {
+        return Err(RigCtlError::InvalidArgument(format!("Invalid command name \"{}\"", command.escape_debug())));
+    }
+
+    if let Some(arg) = args.iter().find(|arg| arg.contains(['\n', '\r'])) {
+        return Err(RigCtlError::InvalidArgument(format!("Line break in argument \"{}\"", arg.escape_debug())));
+    }
+
+    let mut line = command.to_string();
+    for arg in args {
+        line.push(' ');
+        line.push_str(arg);
+    }
+
+    Ok(line)
+}
+
+pub fn parse(line: &str) -> Result<Response, RigCtlError> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"^(?P<command>[^:|]+): ?(?P<args>[^|]*)\|(?P<fields>(?:[^|]*\|)*)RPRT (?P<code>-?[0-9]+)$").unwrap();
+    }
+
+    let captures = RE.captures(line).ok_or(RigCtlError::ResponseParsing("Unable to capture groups".to_string()))?;
+    log::trace!("Captures: {:?}", &captures);
+
+    let command = captures.name("command").ok_or(RigCtlError::ResponseParsing("Invalid command group".to_string()))?.as_str();
+    let args = captures.name("args").ok_or(RigCtlError::ResponseParsing("Invalid args group".to_string()))?.as_str();
+    let fields = captures.name("fields").ok_or(RigCtlError::ResponseParsing("Invalid fields group".to_string()))?.as_str();
+    let code = captures.name("code").ok_or(RigCtlError::ResponseParsing("Invalid code group".to_string()))?.as_str();
+
+    Ok(Response {
+        command: command.to_string(),
+        args: args.to_string(),
+        fields: fields.split_terminator('|')
+            .map(|field| match field.split_once(": ") {
+                Some((name, value)) => Field { name: name.to_string(), value: value.to_string() },
+                None => Field { name: String::new(), value: field.to_string() },
+            })
+            .collect(),
+        code: code.parse::<i32>().map_err(|e| { RigCtlError::ResponseParsing(e.to_string()) })?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_raw() {
+        let input = r"get_level: VFOA STRENGTH|STRENGTH: -54|RPRT 0";
+        let actual = parse(input).unwrap();
+        assert_eq!(actual.command, "get_level");
+        assert_eq!(actual.args, "VFOA STRENGTH");
+        assert_eq!(actual.field("STRENGTH"), Some("-54"));
+        assert!(actual.is_ok());
+    }
+
+    #[test]
+    fn test_raw_error() {
+        let input = r"set_level: VFOA RFPOWER 2|RPRT -1";
+        let actual = parse(input).unwrap();
+        assert!(actual.fields.is_empty());
+        assert_eq!(actual.code, -1);
+    }
+
+    #[test]
+    fn test_compose_rejects_line_breaks() {
+        assert_eq!(compose("set_level", &["VFOA", "RFPOWER", "0.5"]).unwrap(), "set_level VFOA RFPOWER 0.5");
+        assert!(compose("set_level", &["VFOA\nset_ptt", "1"]).is_err());
+        assert!(compose("set_ptt 1", &[]).is_err());
+    }
+}
@@ -0,0 +1,51 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use crate::error::RigCtlError;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+pub fn parse(line: &str) -> Result<(), RigCtlError> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"^set_freq: [^|]*\|RPRT 0$").unwrap();
+    }
+
+    if !RE.is_match(line) {
+        return Err(RigCtlError::ResponseParsing("Unable to match response".to_string()));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_freq() {
+        let input = r"set_freq: VFOA 14074000|RPRT 0";
+        let actual = parse(input);
+        assert!(actual.is_ok());
+    }
+
+    #[test]
+    fn test_set_freq_invalid() {
+        let input = r"get_freq: VFOA|Frequency: 14074000|RPRT 0";
+        let actual = parse(input);
+        assert!(actual.is_err());
+    }
+}
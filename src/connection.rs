@@ -0,0 +1,264 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::error::RigCtlError;
+use socket2::{SockRef, TcpKeepalive};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::task::JoinSet;
+use tokio::time;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TcpOptions {
+    pub connect_timeout: Option<Duration>,
+    pub nodelay: bool,
+    pub keepalive: Option<Duration>,
+    pub happy_eyeballs_delay: Duration,
+}
+
+impl Default for TcpOptions {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Some(Duration::from_secs(5)),
+            nodelay: true,
+            keepalive: None,
+            happy_eyeballs_delay: Duration::from_millis(250),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconnectPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: f64,
+    pub jitter: f64,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let base = self.initial_backoff.as_secs_f64() * self.multiplier.max(1.0).powi(exponent);
+        let capped = base.min(self.max_backoff.as_secs_f64());
+
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = 1.0 + jitter * (2.0 * fastrand::f64() - 1.0);
+
+        Duration::from_secs_f64((capped * factor).max(0.0))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionEvent {
+    Connected,
+    Disconnected,
+    Reconnecting { attempt: u32, delay: Duration },
+    Reconnected { attempts: u32 },
+    ReconnectFailed { attempts: u32 },
+}
+
+impl Display for ConnectionEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionEvent::Connected => { write!(f, "Connected") }
+            ConnectionEvent::Disconnected => { write!(f, "Disconnected") }
+            ConnectionEvent::Reconnecting { attempt, delay } => { write!(f, "Reconnecting (attempt {} in {} ms)", attempt, delay.as_millis()) }
+            ConnectionEvent::Reconnected { attempts } => { write!(f, "Reconnected after {} attempts", attempts) }
+            ConnectionEvent::ReconnectFailed { attempts } => { write!(f, "Reconnect failed after {} attempts", attempts) }
+        }
+    }
+}
+
+pub(crate) async fn connect_tcp(host: &str, port: u16, options: &TcpOptions) -> Result<TcpStream, RigCtlError> {
+    let attempt = async {
+        let addresses = tokio::net::lookup_host((host, port)).await?.collect::<Vec<SocketAddr>>();
+        happy_eyeballs(interleave(addresses), options.happy_eyeballs_delay).await
+    };
+
+    let stream = match options.connect_timeout {
+        Some(timeout) => time::timeout(timeout, attempt).await.map_err(|_| RigCtlError::CommunicationTimeout)??,
+        None => attempt.await?,
+    };
+
+    stream.set_nodelay(options.nodelay)?;
+
+    if let Some(interval) = options.keepalive {
+        let keepalive = TcpKeepalive::new()
+            .with_time(interval)
+            .with_interval(interval);
+        SockRef::from(&stream).set_tcp_keepalive(&keepalive)?;
+    }
+
+    Ok(stream)
+}
+
+async fn happy_eyeballs(addresses: Vec<SocketAddr>, delay: Duration) -> Result<TcpStream, RigCtlError> {
+    let mut pending = addresses.into_iter();
+    let mut attempts = JoinSet::new();
+    let mut last_error = RigCtlError::ConnectionError("No addresses to connect to".to_string());
+
+    match pending.next() {
+        Some(address) => { attempts.spawn(TcpStream::connect(address)); }
+        None => return Err(last_error),
+    }
+
+    loop {
+        let next_attempt = time::sleep(delay);
+
+        tokio::select! {
+            result = attempts.join_next() => {
+                match result {
+                    Some(Ok(Ok(stream))) => return Ok(stream),
+                    Some(Ok(Err(e))) => last_error = RigCtlError::from(e),
+                    Some(Err(e)) => last_error = RigCtlError::ConnectionError(e.to_string()),
+                    None => {}
+                }
+
+                match pending.next() {
+                    Some(address) => { attempts.spawn(TcpStream::connect(address)); }
+                    None if attempts.is_empty() => return Err(last_error),
+                    None => {}
+                }
+            }
+            _ = next_attempt, if pending.len() > 0 => {
+                if let Some(address) = pending.next() {
+                    log::debug!("Starting parallel connection attempt to {}", address);
+                    attempts.spawn(TcpStream::connect(address));
+                }
+            }
+        }
+    }
+}
+
+fn interleave(addresses: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let preferred_ipv6 = addresses.first().map(|address| address.is_ipv6()).unwrap_or(false);
+    let (mut first, mut second): (Vec<SocketAddr>, Vec<SocketAddr>) = addresses.into_iter()
+        .partition(|address| address.is_ipv6() == preferred_ipv6);
+    first.reverse();
+    second.reverse();
+
+    let mut result = Vec::with_capacity(first.len() + second.len());
+    while let Some(address) = first.pop() {
+        result.push(address);
+        if let Some(address) = second.pop() {
+            result.push(address);
+        }
+    }
+    result.extend(second.into_iter().rev());
+
+    result
+}
+
+// Read-only commands that are safe to send again after a reconnect. Sets are
+// left out: the rig may have been retuned during the outage, so the caller
+// gets the error and decides whether the set still applies
+const REPLAYABLE: &[&str] = &[
+    "get_info", "get_freq", "get_mode", "get_vfo", "get_ptt", "get_split_vfo", "get_split_freq", "get_split_mode",
+    "get_level", "get_rit", "get_xit", "get_trn", "get_cache", "dump_state",
+];
+
+pub(crate) fn is_replayable(command: &str) -> bool {
+    let name = command.split_whitespace().next().unwrap_or_default();
+    REPLAYABLE.contains(&name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_without_jitter() {
+        let policy = ReconnectPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(1000),
+            multiplier: 2.0,
+            jitter: 0.0,
+        };
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(4), Duration::from_millis(800));
+        assert_eq!(policy.backoff(5), Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn test_backoff_with_jitter() {
+        let policy = ReconnectPolicy {
+            initial_backoff: Duration::from_millis(1000),
+            jitter: 0.5,
+            ..Default::default()
+        };
+        for _ in 0..100 {
+            let delay = policy.backoff(1);
+            assert!(delay >= Duration::from_millis(500));
+            assert!(delay <= Duration::from_millis(1500));
+        }
+    }
+
+    #[test]
+    fn test_interleave_address_families() {
+        let addresses = vec![
+            "[::1]:4532".parse().unwrap(),
+            "[::2]:4532".parse().unwrap(),
+            "[::3]:4532".parse().unwrap(),
+            "127.0.0.1:4532".parse().unwrap(),
+        ];
+        let expected: Vec<SocketAddr> = vec![
+            "[::1]:4532".parse().unwrap(),
+            "127.0.0.1:4532".parse().unwrap(),
+            "[::2]:4532".parse().unwrap(),
+            "[::3]:4532".parse().unwrap(),
+        ];
+        assert_eq!(interleave(addresses), expected);
+    }
+
+    #[tokio::test]
+    async fn test_happy_eyeballs_skips_unreachable_address() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+        let addresses = vec![closed, SocketAddr::from(([127, 0, 0, 1], port))];
+        let stream = happy_eyeballs(addresses, Duration::from_millis(50)).await;
+        assert!(stream.is_ok());
+    }
+
+    #[test]
+    fn test_is_replayable() {
+        assert!(is_replayable("get_freq VFOA"));
+        assert!(is_replayable("dump_state"));
+        assert!(!is_replayable("set_freq VFOA 7074000"));
+        assert!(!is_replayable("set_ptt VFOA 1"));
+        assert!(!is_replayable("send_morse VFOA CQ"));
+        assert!(!is_replayable("send_cmd \\0x00"));
+    }
+}
@@ -23,9 +23,14 @@ use std::string::FromUtf8Error;
 pub enum RigCtlError {
     ConnectionError(String),
     AlreadyConnected,
+    NotConnected,
+    AuthenticationFailed,
+    AuthenticationUnsupported(String),
     RawDataError(String),
+    InvalidArgument(String),
     ResponseParsing(String),
     CommunicationTimeout,
+    HamlibError(ReturnCode),
 }
 
 impl Display for RigCtlError {
@@ -33,9 +38,14 @@ impl Display for RigCtlError {
         match self {
             RigCtlError::ConnectionError(message) => { write!(f, "Connection error: {}", message) }
             RigCtlError::AlreadyConnected => { write!(f, "Already connected") }
+            RigCtlError::NotConnected => { write!(f, "Not connected") }
+            RigCtlError::AuthenticationFailed => { write!(f, "Authentication failed") }
+            RigCtlError::AuthenticationUnsupported(method) => { write!(f, "Authentication with {} not supported by this rigctld", method) }
             RigCtlError::RawDataError(message) => { write!(f, "Raw data error: {}", message) }
+            RigCtlError::InvalidArgument(message) => { write!(f, "Invalid argument: {}", message) }
             RigCtlError::ResponseParsing(message) => { write!(f, "Response parsing error: {}", message) }
             RigCtlError::CommunicationTimeout => { write!(f, "Communication timeout") }
+            RigCtlError::HamlibError(code) => { write!(f, "Hamlib error: {}", code) }
         }
     }
 }
@@ -51,3 +61,112 @@ impl From<FromUtf8Error> for RigCtlError {
         RigCtlError::RawDataError(value.to_string())
     }
 }
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReturnCode {
+    EINVAL,
+    ECONF,
+    ENOMEM,
+    ENIMPL,
+    ETIMEOUT,
+    EIO,
+    EINTERNAL,
+    EPROTO,
+    ERJCTED,
+    ETRUNC,
+    ENAVAIL,
+    ENTARGET,
+    BUSERROR,
+    BUSBUSY,
+    EARG,
+    EVFO,
+    EDOM,
+    EDEPRECATED,
+    ESECURITY,
+    EPOWER,
+    Unknown(i32),
+}
+
+impl ReturnCode {
+    pub fn code(&self) -> i32 {
+        match self {
+            Self::EINVAL => -1,
+            Self::ECONF => -2,
+            Self::ENOMEM => -3,
+            Self::ENIMPL => -4,
+            Self::ETIMEOUT => -5,
+            Self::EIO => -6,
+            Self::EINTERNAL => -7,
+            Self::EPROTO => -8,
+            Self::ERJCTED => -9,
+            Self::ETRUNC => -10,
+            Self::ENAVAIL => -11,
+            Self::ENTARGET => -12,
+            Self::BUSERROR => -13,
+            Self::BUSBUSY => -14,
+            Self::EARG => -15,
+            Self::EVFO => -16,
+            Self::EDOM => -17,
+            Self::EDEPRECATED => -18,
+            Self::ESECURITY => -19,
+            Self::EPOWER => -20,
+            Self::Unknown(code) => *code,
+        }
+    }
+}
+
+impl From<i32> for ReturnCode {
+    fn from(value: i32) -> Self {
+        match value {
+            -1 => Self::EINVAL,
+            -2 => Self::ECONF,
+            -3 => Self::ENOMEM,
+            -4 => Self::ENIMPL,
+            -5 => Self::ETIMEOUT,
+            -6 => Self::EIO,
+            -7 => Self::EINTERNAL,
+            -8 => Self::EPROTO,
+            -9 => Self::ERJCTED,
+            -10 => Self::ETRUNC,
+            -11 => Self::ENAVAIL,
+            -12 => Self::ENTARGET,
+            -13 => Self::BUSERROR,
+            -14 => Self::BUSBUSY,
+            -15 => Self::EARG,
+            -16 => Self::EVFO,
+            -17 => Self::EDOM,
+            -18 => Self::EDEPRECATED,
+            -19 => Self::ESECURITY,
+            -20 => Self::EPOWER,
+            _ => Self::Unknown(value),
+        }
+    }
+}
+
+impl Display for ReturnCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EINVAL => { write!(f, "Invalid parameter") }
+            Self::ECONF => { write!(f, "Invalid configuration") }
+            Self::ENOMEM => { write!(f, "Memory shortage") }
+            Self::ENIMPL => { write!(f, "Function not implemented") }
+            Self::ETIMEOUT => { write!(f, "Communication timed out") }
+            Self::EIO => { write!(f, "IO error") }
+            Self::EINTERNAL => { write!(f, "Internal Hamlib error") }
+            Self::EPROTO => { write!(f, "Protocol error") }
+            Self::ERJCTED => { write!(f, "Command rejected by the rig") }
+            Self::ETRUNC => { write!(f, "Command performed, but arg truncated") }
+            Self::ENAVAIL => { write!(f, "Function not available") }
+            Self::ENTARGET => { write!(f, "VFO not targetable") }
+            Self::BUSERROR => { write!(f, "Error talking on the bus") }
+            Self::BUSBUSY => { write!(f, "Collision on the bus") }
+            Self::EARG => { write!(f, "Invalid arg passed") }
+            Self::EVFO => { write!(f, "Invalid VFO") }
+            Self::EDOM => { write!(f, "Argument out of domain of func") }
+            Self::EDEPRECATED => { write!(f, "Function deprecated") }
+            Self::ESECURITY => { write!(f, "Security error") }
+            Self::EPOWER => { write!(f, "Rig not powered on") }
+            Self::Unknown(code) => { write!(f, "Unknown error code {}", code) }
+        }
+    }
+}
@@ -0,0 +1,108 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use crate::mode::Mode;
+use crate::ptt::PTT;
+use crate::vfo::VFO;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+pub(crate) const EVENT_PREFIX: &str = "Event: ";
+
+// A change of rig state, as seen by `watcher::RigWatcher`, pushed by the rig
+// in transceive mode or derived from `multicast` state packets
+#[derive(Debug, Clone, PartialEq)]
+pub enum RigEvent {
+    FrequencyChanged(u64),
+    ModeChanged { mode: Mode, passband: u64 },
+    VfoChanged(VFO),
+    SplitChanged { split: bool, tx_vfo: VFO },
+    PttChanged(PTT),
+    Disconnected,
+}
+
+impl Display for RigEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RigEvent::FrequencyChanged(frequency) => { write!(f, "Frequency: {}", frequency) }
+            RigEvent::ModeChanged { mode, passband } => { write!(f, "Mode: {} - Passband: {}", mode, passband) }
+            RigEvent::VfoChanged(vfo) => { write!(f, "VFO: {}", vfo) }
+            RigEvent::SplitChanged { split, tx_vfo } => { write!(f, "Split: {} - TX VFO: {}", split, tx_vfo) }
+            RigEvent::PttChanged(ptt) => { write!(f, "PTT: {}", ptt) }
+            RigEvent::Disconnected => { write!(f, "Disconnected") }
+        }
+    }
+}
+
+// Unsolicited lines pushed by Hamlib in transceive mode, as printed by its
+// event callbacks, e.g. "Event: freq changed to 14074000Hz on VFOA"
+pub fn is_event(line: &str) -> bool {
+    line.starts_with(EVENT_PREFIX)
+}
+
+// Returns None for events with no RigEvent counterpart (such as DCD) and
+// for lines that cannot be parsed
+pub fn parse(line: &str) -> Option<RigEvent> {
+    lazy_static! {
+        static ref FREQ: Regex = Regex::new(r"^freq changed to (?P<frequency>[0-9]+)(?:\.[0-9]+)?Hz").unwrap();
+        static ref MODE: Regex = Regex::new(r"^mode changed to (?P<mode>[A-Z0-9]+), width (?P<passband>[0-9]+)Hz").unwrap();
+        static ref VFO_RE: Regex = Regex::new(r"^vfo changed to (?P<vfo>[a-zA-Z0-9]+)").unwrap();
+        static ref PTT_RE: Regex = Regex::new(r"^PTT changed to (?P<ptt>[0-3])").unwrap();
+    }
+
+    let event = line.strip_prefix(EVENT_PREFIX)?.trim();
+    log::trace!("Event: {}", event);
+
+    if let Some(captures) = FREQ.captures(event) {
+        return captures["frequency"].parse::<u64>().ok().map(RigEvent::FrequencyChanged);
+    }
+
+    if let Some(captures) = MODE.captures(event) {
+        return Some(RigEvent::ModeChanged {
+            mode: Mode::from_str(&captures["mode"]).ok()?,
+            passband: captures["passband"].parse::<u64>().ok()?,
+        });
+    }
+
+    if let Some(captures) = VFO_RE.captures(event) {
+        return VFO::from_str(&captures["vfo"]).ok().map(RigEvent::VfoChanged);
+    }
+
+    if let Some(captures) = PTT_RE.captures(event) {
+        return PTT::from_str(&captures["ptt"]).ok().map(RigEvent::PttChanged);
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_events() {
+        assert_eq!(parse("Event: freq changed to 14074000Hz on VFOA"), Some(RigEvent::FrequencyChanged(14074000)));
+        assert_eq!(parse("Event: mode changed to USB, width 2400Hz on VFOA"), Some(RigEvent::ModeChanged { mode: Mode::USB, passband: 2400 }));
+        assert_eq!(parse("Event: vfo changed to VFOB"), Some(RigEvent::VfoChanged(VFO::VFOB)));
+        assert_eq!(parse("Event: PTT changed to 1 on VFOA"), Some(RigEvent::PttChanged(PTT::TX)));
+        assert_eq!(parse("Event: DCD changed to 1 on VFOA"), None);
+        assert_eq!(parse("get_freq: VFOA|Frequency: 14074000|RPRT 0"), None);
+    }
+}
@@ -0,0 +1,241 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use crate::auth::Credentials;
+use crate::commands;
+use crate::commands::password;
+use crate::commands::{get_cache, get_freq, get_info, get_level, get_mode, get_rit, get_split_freq, get_split_mode, get_split_vfo, get_vfo, get_ptt, get_xit, set_cache, set_freq, set_mode, set_ptt};
+use crate::error::RigCtlError;
+use crate::mode::Mode;
+use crate::protocol;
+use crate::protocol::{Command, Decoder, ProtocolMode};
+use crate::ptt::PTT;
+use crate::vfo::VFO;
+use futures_lite::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+// Runtime-agnostic client over any futures-io stream (async-std, smol, ...).
+// No timer is available here, so timeouts are left to the caller's runtime.
+pub struct RigCtlClient<S> {
+    stream: S,
+    decoder: Decoder,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> RigCtlClient<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            decoder: Decoder::new(ProtocolMode::default()),
+        }
+    }
+
+    pub fn with_protocol_mode(stream: S, mode: ProtocolMode) -> Self {
+        Self {
+            stream,
+            decoder: Decoder::new(mode),
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    pub async fn authenticate(&mut self, credentials: &Credentials) -> Result<(), RigCtlError> {
+        let command = credentials.command()?;
+        let data = protocol::encode(self.decoder.mode(), std::slice::from_ref(&command));
+        self.stream.write_all(&data).await?;
+        self.stream.flush().await?;
+
+        let line = self.read_line(&command).await?;
+        password::parse(&line)
+    }
+
+    pub async fn get_info(&mut self) -> Result<get_info::Response, RigCtlError> {
+        let cmd = Command::GetInfo.to_string();
+        let response = self.execute_command(&cmd).await?;
+        get_info::parse(&response)
+    }
+
+    pub async fn get_mode(&mut self, vfo: VFO) -> Result<get_mode::Response, RigCtlError> {
+        let cmd = Command::GetMode(vfo).to_string();
+        let response = self.execute_command(&cmd).await?;
+        get_mode::parse(&response)
+    }
+
+    pub async fn get_freq(&mut self, vfo: VFO) -> Result<get_freq::Response, RigCtlError> {
+        let cmd = Command::GetFreq(vfo).to_string();
+        let response = self.execute_command(&cmd).await?;
+        get_freq::parse(&response)
+    }
+
+    pub async fn get_vfo(&mut self) -> Result<get_vfo::Response, RigCtlError> {
+        let cmd = Command::GetVfo.to_string();
+        let response = self.execute_command(&cmd).await?;
+        get_vfo::parse(&response)
+    }
+
+    pub async fn get_split_vfo(&mut self) -> Result<get_split_vfo::Response, RigCtlError> {
+        let cmd = Command::GetSplitVfo.to_string();
+        let response = self.execute_command(&cmd).await?;
+        get_split_vfo::parse(&response)
+    }
+
+    pub async fn get_split_mode(&mut self, vfo: VFO) -> Result<get_split_mode::Response, RigCtlError> {
+        let cmd = Command::GetSplitMode(vfo).to_string();
+        let response = self.execute_command(&cmd).await?;
+        get_split_mode::parse(&response)
+    }
+
+    pub async fn get_split_freq(&mut self, vfo: VFO) -> Result<get_split_freq::Response, RigCtlError> {
+        let cmd = Command::GetSplitFreq(vfo).to_string();
+        let response = self.execute_command(&cmd).await?;
+        get_split_freq::parse(&response)
+    }
+
+    pub async fn set_ptt(&mut self, vfo: VFO, ptt: PTT) -> Result<(), RigCtlError> {
+        let cmd = Command::SetPtt(vfo, ptt).to_string();
+        let response = self.execute_command(&cmd).await?;
+        set_ptt::parse(&response)
+    }
+
+    pub async fn get_ptt(&mut self, vfo: VFO) -> Result<get_ptt::Response, RigCtlError> {
+        let cmd = Command::GetPtt(vfo).to_string();
+        let response = self.execute_command(&cmd).await?;
+        get_ptt::parse(&response)
+    }
+
+    pub async fn set_freq(&mut self, vfo: VFO, frequency: u64) -> Result<(), RigCtlError> {
+        let cmd = Command::SetFreq(vfo, frequency).to_string();
+        let response = self.execute_command(&cmd).await?;
+        set_freq::parse(&response)
+    }
+
+    pub async fn set_mode(&mut self, vfo: VFO, mode: Mode, passband: u64) -> Result<(), RigCtlError> {
+        let cmd = Command::SetMode(vfo, mode, passband).to_string();
+        let response = self.execute_command(&cmd).await?;
+        set_mode::parse(&response)
+    }
+
+    pub async fn get_level(&mut self, vfo: VFO, level: &'static str) -> Result<get_level::Response, RigCtlError> {
+        let cmd = Command::GetLevel(vfo, level).to_string();
+        let response = self.execute_command(&cmd).await?;
+        get_level::parse(&response)
+    }
+
+    pub async fn get_rit(&mut self, vfo: VFO) -> Result<get_rit::Response, RigCtlError> {
+        let cmd = Command::GetRit(vfo).to_string();
+        let response = self.execute_command(&cmd).await?;
+        get_rit::parse(&response)
+    }
+
+    pub async fn get_xit(&mut self, vfo: VFO) -> Result<get_xit::Response, RigCtlError> {
+        let cmd = Command::GetXit(vfo).to_string();
+        let response = self.execute_command(&cmd).await?;
+        get_xit::parse(&response)
+    }
+
+    pub async fn get_cache(&mut self) -> Result<get_cache::Response, RigCtlError> {
+        let cmd = Command::GetCache.to_string();
+        let response = self.execute_command(&cmd).await?;
+        get_cache::parse(&response)
+    }
+
+    pub async fn set_cache(&mut self, timeout: u64) -> Result<(), RigCtlError> {
+        let cmd = Command::SetCache(timeout).to_string();
+        let response = self.execute_command(&cmd).await?;
+        set_cache::parse(&response)
+    }
+
+    async fn execute_command(&mut self, command: &str) -> Result<String, RigCtlError> {
+        let data = protocol::encode(self.decoder.mode(), &[command.to_string()]);
+        log::trace!(" >>> [{}] ({} bytes)", protocol::redact(String::from_utf8_lossy(&data).trim_end()), data.len());
+        self.stream.write_all(&data).await?;
+        self.stream.flush().await?;
+
+        let line = self.read_line(command).await?;
+        commands::check_return_code(&line)?;
+        Ok(line)
+    }
+
+    async fn read_line(&mut self, command: &str) -> Result<String, RigCtlError> {
+        log::debug!("Reading line");
+
+        loop {
+            if let Some(line) = self.decoder.next_response(command)? {
+                log::trace!(" <<< [{}] ({} bytes)", protocol::redact(&line), line.len());
+                return Ok(line);
+            }
+
+            let mut buf = [0u8; 4096];
+            let bytes_read = self.stream.read(&mut buf).await?;
+
+            if bytes_read == 0 {
+                return Err(RigCtlError::ConnectionError("Connection closed by remote host".to_string()));
+            }
+
+            self.decoder.feed(&buf[0..bytes_read]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_lite::future::block_on;
+    use futures_lite::io::Cursor;
+    use std::io;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    struct Canned {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl AsyncRead for Canned {
+        fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+            Pin::new(&mut self.input).poll_read(cx, buf)
+        }
+    }
+
+    impl AsyncWrite for Canned {
+        fn poll_write(mut self: Pin<&mut Self>, _: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+            self.output.extend_from_slice(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn test_futures_client_get_freq() {
+        let stream = Canned {
+            input: Cursor::new(b"get_freq: VFOA|Frequency: 14074000|RPRT 0\n".to_vec()),
+            output: Vec::new(),
+        };
+        let mut client = RigCtlClient::new(stream);
+
+        let actual = block_on(client.get_freq(VFO::VFOA)).unwrap();
+        assert_eq!(actual, get_freq::Response { query_vfo: VFO::VFOA, frequency: 14074000u64 });
+        assert_eq!(client.into_inner().output, b"|\\get_freq VFOA\n".to_vec());
+    }
+}
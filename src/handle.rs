@@ -0,0 +1,386 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use crate::cat;
+use crate::cat::CatReply;
+use crate::commands::{get_cache, get_freq, get_info, get_level, get_mode, get_rit, get_split_freq, get_split_mode, get_split_vfo, get_vfo, get_ptt, get_xit, raw, set_cache, set_freq, set_mode, set_ptt};
+use crate::connection::ConnectionEvent;
+use crate::error::RigCtlError;
+use crate::event::RigEvent;
+use crate::mode::Mode;
+use crate::protocol::Command;
+use crate::ptt::PTT;
+use crate::stats::ClientStats;
+use crate::vfo::VFO;
+use crate::RigCtlClient;
+use tokio::sync::{broadcast, mpsc, oneshot};
+
+const DEFAULT_QUEUE_CAPACITY: usize = 32;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Priority {
+    Normal,
+    High,
+}
+
+enum Message {
+    Connect(oneshot::Sender<Result<(), RigCtlError>>),
+    Disconnect(oneshot::Sender<()>),
+    IsConnected(oneshot::Sender<bool>),
+    Stats(oneshot::Sender<ClientStats>),
+    Transceive(bool, oneshot::Sender<Result<(), RigCtlError>>),
+    Command(String, oneshot::Sender<Result<String, RigCtlError>>),
+    Raw(String, Vec<String>, oneshot::Sender<Result<raw::Response, RigCtlError>>),
+}
+
+#[derive(Clone)]
+pub struct RigCtlHandle {
+    normal: mpsc::Sender<Message>,
+    high: mpsc::Sender<Message>,
+    events: broadcast::Sender<ConnectionEvent>,
+    rig_events: broadcast::Sender<RigEvent>,
+}
+
+impl RigCtlHandle {
+    pub fn spawn(client: RigCtlClient) -> Self {
+        Self::spawn_with_capacity(client, DEFAULT_QUEUE_CAPACITY)
+    }
+
+    pub fn spawn_with_capacity(client: RigCtlClient, capacity: usize) -> Self {
+        let (normal, normal_rx) = mpsc::channel(capacity);
+        let (high, high_rx) = mpsc::channel(capacity);
+        let events = client.events.clone();
+        let rig_events = client.rig_events.clone();
+
+        tokio::spawn(run(client, normal_rx, high_rx));
+
+        Self { normal, high, events, rig_events }
+    }
+
+    pub async fn connect(&self) -> Result<(), RigCtlError> {
+        let (tx, rx) = oneshot::channel();
+        self.send(Message::Connect(tx), Priority::Normal).await?;
+        rx.await.map_err(|_| terminated())?
+    }
+
+    pub async fn disconnect(&self) -> Result<(), RigCtlError> {
+        let (tx, rx) = oneshot::channel();
+        self.send(Message::Disconnect(tx), Priority::Normal).await?;
+        rx.await.map_err(|_| terminated())
+    }
+
+    pub async fn is_connected(&self) -> Result<bool, RigCtlError> {
+        let (tx, rx) = oneshot::channel();
+        self.send(Message::IsConnected(tx), Priority::Normal).await?;
+        rx.await.map_err(|_| terminated())
+    }
+
+    pub async fn stats(&self) -> Result<ClientStats, RigCtlError> {
+        let (tx, rx) = oneshot::channel();
+        self.send(Message::Stats(tx), Priority::Normal).await?;
+        rx.await.map_err(|_| terminated())
+    }
+
+    pub fn subscribe_connection_events(&self) -> broadcast::Receiver<ConnectionEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn subscribe_rig_events(&self) -> broadcast::Receiver<RigEvent> {
+        self.rig_events.subscribe()
+    }
+
+    // While transceive is on, the client task also listens for pushed events
+    // between commands
+    pub async fn set_transceive(&self, enabled: bool) -> Result<(), RigCtlError> {
+        let (tx, rx) = oneshot::channel();
+        self.send(Message::Transceive(enabled, tx), Priority::Normal).await?;
+        rx.await.map_err(|_| terminated())?
+    }
+
+    pub async fn get_info(&self) -> Result<get_info::Response, RigCtlError> {
+        let response = self.execute(Command::GetInfo, Priority::Normal).await?;
+        get_info::parse(&response)
+    }
+
+    pub async fn get_mode(&self, vfo: VFO) -> Result<get_mode::Response, RigCtlError> {
+        let response = self.execute(Command::GetMode(vfo), Priority::Normal).await?;
+        get_mode::parse(&response)
+    }
+
+    pub async fn get_freq(&self, vfo: VFO) -> Result<get_freq::Response, RigCtlError> {
+        let response = self.execute(Command::GetFreq(vfo), Priority::Normal).await?;
+        get_freq::parse(&response)
+    }
+
+    pub async fn get_vfo(&self) -> Result<get_vfo::Response, RigCtlError> {
+        let response = self.execute(Command::GetVfo, Priority::Normal).await?;
+        get_vfo::parse(&response)
+    }
+
+    pub async fn get_split_vfo(&self) -> Result<get_split_vfo::Response, RigCtlError> {
+        let response = self.execute(Command::GetSplitVfo, Priority::Normal).await?;
+        get_split_vfo::parse(&response)
+    }
+
+    pub async fn get_split_mode(&self, vfo: VFO) -> Result<get_split_mode::Response, RigCtlError> {
+        let response = self.execute(Command::GetSplitMode(vfo), Priority::Normal).await?;
+        get_split_mode::parse(&response)
+    }
+
+    pub async fn get_split_freq(&self, vfo: VFO) -> Result<get_split_freq::Response, RigCtlError> {
+        let response = self.execute(Command::GetSplitFreq(vfo), Priority::Normal).await?;
+        get_split_freq::parse(&response)
+    }
+
+    // PTT changes always take the high priority queue, so that a queued TX can
+    // never overtake a later RX
+    pub async fn set_ptt(&self, vfo: VFO, ptt: PTT) -> Result<(), RigCtlError> {
+        let response = self.execute(Command::SetPtt(vfo, ptt), Priority::High).await?;
+        set_ptt::parse(&response)
+    }
+
+    pub async fn get_ptt(&self, vfo: VFO) -> Result<get_ptt::Response, RigCtlError> {
+        let response = self.execute(Command::GetPtt(vfo), Priority::Normal).await?;
+        get_ptt::parse(&response)
+    }
+
+    pub async fn set_freq(&self, vfo: VFO, frequency: u64) -> Result<(), RigCtlError> {
+        let response = self.execute(Command::SetFreq(vfo, frequency), Priority::Normal).await?;
+        set_freq::parse(&response)
+    }
+
+    pub async fn set_mode(&self, vfo: VFO, mode: Mode, passband: u64) -> Result<(), RigCtlError> {
+        let response = self.execute(Command::SetMode(vfo, mode, passband), Priority::Normal).await?;
+        set_mode::parse(&response)
+    }
+
+    pub async fn get_level(&self, vfo: VFO, level: &'static str) -> Result<get_level::Response, RigCtlError> {
+        let response = self.execute(Command::GetLevel(vfo, level), Priority::Normal).await?;
+        get_level::parse(&response)
+    }
+
+    pub async fn get_rit(&self, vfo: VFO) -> Result<get_rit::Response, RigCtlError> {
+        let response = self.execute(Command::GetRit(vfo), Priority::Normal).await?;
+        get_rit::parse(&response)
+    }
+
+    pub async fn get_xit(&self, vfo: VFO) -> Result<get_xit::Response, RigCtlError> {
+        let response = self.execute(Command::GetXit(vfo), Priority::Normal).await?;
+        get_xit::parse(&response)
+    }
+
+    pub async fn get_cache(&self) -> Result<get_cache::Response, RigCtlError> {
+        let response = self.execute(Command::GetCache, Priority::Normal).await?;
+        get_cache::parse(&response)
+    }
+
+    pub async fn set_cache(&self, timeout: u64) -> Result<(), RigCtlError> {
+        let response = self.execute(Command::SetCache(timeout), Priority::Normal).await?;
+        set_cache::parse(&response)
+    }
+
+    pub async fn execute_raw(&self, command: &str, args: &[&str]) -> Result<raw::Response, RigCtlError> {
+        raw::compose(command, args)?;
+        let (tx, rx) = oneshot::channel();
+        let args = args.iter().map(|arg| arg.to_string()).collect();
+        self.send(Message::Raw(command.to_string(), args, tx), Priority::Normal).await?;
+        rx.await.map_err(|_| terminated())?
+    }
+
+    pub async fn send_cat(&self, data: &[u8], reply: Option<CatReply>) -> Result<Vec<u8>, RigCtlError> {
+        let (command, args) = cat::compose(data, reply)?;
+        let args = args.iter().map(String::as_str).collect::<Vec<&str>>();
+        let response = self.execute_raw(command, &args).await?;
+        cat::parse_reply(&response)
+    }
+
+    async fn execute(&self, command: Command, priority: Priority) -> Result<String, RigCtlError> {
+        self.execute_line(command.to_string(), priority).await
+    }
+
+    // Runs an already composed command line and returns the normalized reply
+    #[cfg(feature = "proxy")]
+    pub(crate) async fn execute_command(&self, command: &str) -> Result<String, RigCtlError> {
+        let priority = match command.starts_with("set_ptt") {
+            true => Priority::High,
+            false => Priority::Normal,
+        };
+        self.execute_line(command.to_string(), priority).await
+    }
+
+    async fn execute_line(&self, command: String, priority: Priority) -> Result<String, RigCtlError> {
+        let (tx, rx) = oneshot::channel();
+        self.send(Message::Command(command, tx), priority).await?;
+        rx.await.map_err(|_| terminated())?
+    }
+
+    async fn send(&self, message: Message, priority: Priority) -> Result<(), RigCtlError> {
+        let queue = match priority {
+            Priority::Normal => &self.normal,
+            Priority::High => &self.high,
+        };
+
+        queue.send(message).await.map_err(|_| terminated())
+    }
+}
+
+async fn run(mut client: RigCtlClient, mut normal: mpsc::Receiver<Message>, mut high: mpsc::Receiver<Message>) {
+    log::debug!("Client task started");
+
+    loop {
+        // Once every handle is gone, stop listening so that the task ends
+        let listening = client.is_transceive() && client.is_connected() && !(high.is_closed() && normal.is_closed());
+        let message = tokio::select! {
+            biased;
+            Some(message) = high.recv() => message,
+            Some(message) = normal.recv() => message,
+            result = client.next_event(), if listening => {
+                if let Err(e) = result {
+                    log::debug!("Stopped listening for rig events: {}", e);
+                }
+                continue;
+            }
+            else => break,
+        };
+
+        match message {
+            Message::Connect(reply) => {
+                let _ = reply.send(client.connect().await);
+            }
+            Message::Disconnect(reply) => {
+                client.disconnect();
+                let _ = reply.send(());
+            }
+            Message::IsConnected(reply) => {
+                let _ = reply.send(client.is_connected());
+            }
+            Message::Stats(reply) => {
+                let _ = reply.send(client.stats());
+            }
+            Message::Transceive(enabled, reply) => {
+                let _ = reply.send(client.set_transceive(enabled).await);
+            }
+            Message::Command(command, reply) => {
+                if reply.is_closed() {
+                    log::debug!("Dropping cancelled command: {}", command);
+                    continue;
+                }
+                let _ = reply.send(client.execute_command(&command).await);
+            }
+            Message::Raw(command, args, reply) => {
+                if reply.is_closed() {
+                    log::debug!("Dropping cancelled raw command: {}", command);
+                    continue;
+                }
+                let args = args.iter().map(String::as_str).collect::<Vec<&str>>();
+                let _ = reply.send(client.execute_raw(&command, &args).await);
+            }
+        }
+    }
+
+    log::debug!("Client task terminated");
+}
+
+fn terminated() -> RigCtlError {
+    RigCtlError::ConnectionError("Client task is no longer running".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ReturnCode;
+    use crate::mock::MockServer;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_handle_shared_between_clones() {
+        let server = MockServer::start().await.unwrap();
+        server.inject_error("get_vfo", ReturnCode::ENIMPL);
+        let handle = RigCtlHandle::spawn(server.client());
+        handle.connect().await.unwrap();
+
+        let other = handle.clone();
+        let (freq, ptt) = tokio::join!(handle.get_freq(VFO::VFOA), other.set_ptt(VFO::VFOA, PTT::RX));
+
+        assert_eq!(freq.unwrap(), get_freq::Response { query_vfo: VFO::VFOA, frequency: 14074000u64 });
+        assert!(ptt.is_ok());
+        assert!(matches!(handle.get_vfo().await, Err(RigCtlError::HamlibError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_ptt_overtakes_queued_polls() {
+        let server = MockServer::start().await.unwrap();
+        let handle = RigCtlHandle::spawn(server.client());
+        handle.connect().await.unwrap();
+        server.set_delay(Some(Duration::from_millis(50)));
+
+        let (done, mut order) = mpsc::unbounded_channel();
+        for _ in 0..4 {
+            let (handle, done) = (handle.clone(), done.clone());
+            tokio::spawn(async move {
+                handle.get_freq(VFO::VFOA).await.unwrap();
+                done.send("get_freq").unwrap();
+            });
+        }
+
+        // Let the polls queue up behind the first one, which is in flight
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        handle.set_ptt(VFO::VFOA, PTT::RX).await.unwrap();
+        done.send("set_ptt").unwrap();
+        drop(done);
+
+        let mut actual = Vec::new();
+        while let Some(command) = order.recv().await {
+            actual.push(command);
+        }
+        assert_eq!(actual, vec!["get_freq", "set_ptt", "get_freq", "get_freq", "get_freq"]);
+    }
+
+    #[tokio::test]
+    async fn test_handle_receives_pushed_events() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let (stream, rig) = tokio::io::duplex(1024);
+        tokio::spawn(async move {
+            let (reader, mut writer) = tokio::io::split(rig);
+            let mut lines = BufReader::new(reader).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                match line.as_str() {
+                    r"|\set_trn RIG" => {
+                        writer.write_all(b"set_trn: RIG|RPRT 0\n").await.unwrap();
+                        // Pushed while the client is idle
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        writer.write_all(b"Event: freq changed to 7074000Hz on VFOA\n").await.unwrap();
+                    }
+                    // Pushed ahead of a reply
+                    r"|\get_freq VFOA" => writer.write_all(b"Event: PTT changed to 1 on VFOA\nget_freq: VFOA|Frequency: 7074000|RPRT 0\n").await.unwrap(),
+                    _ => writer.write_all(b"RPRT -11\n").await.unwrap(),
+                }
+            }
+        });
+
+        let handle = RigCtlHandle::spawn(RigCtlClient::from_stream(stream, None));
+        let mut events = handle.subscribe_rig_events();
+        handle.set_transceive(true).await.unwrap();
+
+        let event = tokio::time::timeout(Duration::from_secs(2), events.recv()).await.unwrap();
+        assert_eq!(event.unwrap(), RigEvent::FrequencyChanged(7074000));
+
+        assert_eq!(handle.get_freq(VFO::VFOA).await.unwrap().frequency, 7074000);
+        assert_eq!(events.try_recv().unwrap(), RigEvent::PttChanged(PTT::TX));
+    }
+}
@@ -0,0 +1,539 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use crate::error::ReturnCode;
+use crate::mode::Mode;
+use crate::protocol;
+use crate::protocol::{arg, parse_arg, parse_frequency, Values};
+use crate::ptt::PTT;
+use crate::vfo::VFO;
+use crate::RigCtlClient;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::net::SocketAddr;
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VfoState {
+    pub frequency: u64,
+    pub mode: Mode,
+    pub passband: u64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Passband {
+    pub default: u64,
+    pub min: u64,
+    pub max: u64,
+}
+
+impl Passband {
+    pub fn new(default: u64, min: u64, max: u64) -> Self {
+        Self { default, min, max }
+    }
+}
+
+// Capabilities of the simulated rig; requests outside of them are rejected
+// with the codes a Hamlib backend would return
+#[derive(Debug, Clone, PartialEq)]
+pub struct RigModel {
+    pub frequency_ranges: HashMap<VFO, Vec<RangeInclusive<u64>>>,
+    pub passbands: HashMap<Mode, Passband>,
+    pub split: bool,
+    pub levels: BTreeMap<String, RangeInclusive<f64>>,
+}
+
+impl Default for RigModel {
+    fn default() -> Self {
+        let hf = vec![30000..=60000000];
+        let ssb = Passband::new(2400, 1800, 3600);
+        let cw = Passband::new(500, 50, 3600);
+
+        Self {
+            frequency_ranges: HashMap::from([(VFO::VFOA, hf.clone()), (VFO::VFOB, hf)]),
+            passbands: HashMap::from([
+                (Mode::USB, ssb),
+                (Mode::LSB, ssb),
+                (Mode::PKTUSB, ssb),
+                (Mode::PKTLSB, ssb),
+                (Mode::CW, cw),
+                (Mode::CWR, cw),
+                (Mode::RTTY, cw),
+                (Mode::RTTYR, cw),
+                (Mode::AM, Passband::new(6000, 3000, 9000)),
+                (Mode::FM, Passband::new(12000, 9000, 15000)),
+            ]),
+            split: true,
+            levels: BTreeMap::from([
+                ("AF".to_string(), 0.0..=1.0),
+                ("RFPOWER".to_string(), 0.0..=1.0),
+                ("STRENGTH".to_string(), -54.0..=60.0),
+            ]),
+        }
+    }
+}
+
+impl RigModel {
+    pub fn modes(&self) -> Vec<Mode> {
+        self.passbands.keys().copied().collect()
+    }
+
+    fn check_frequency(&self, vfo: VFO, frequency: u64) -> Result<(), ReturnCode> {
+        let ranges = self.frequency_ranges.get(&vfo).ok_or(ReturnCode::EINVAL)?;
+        match ranges.iter().any(|range| range.contains(&frequency)) {
+            true => Ok(()),
+            false => Err(ReturnCode::EINVAL),
+        }
+    }
+
+    // Resolves the requested passband: 0 selects the mode default and a
+    // negative value keeps the current one
+    fn check_passband(&self, mode: Mode, passband: i64, current: u64) -> Result<u64, ReturnCode> {
+        let limits = self.passbands.get(&mode).ok_or(ReturnCode::EINVAL)?;
+        match passband {
+            0 => Ok(limits.default),
+            passband if passband < 0 => Ok(current.clamp(limits.min, limits.max)),
+            passband if (limits.min..=limits.max).contains(&(passband as u64)) => Ok(passband as u64),
+            _ => Err(ReturnCode::EINVAL),
+        }
+    }
+
+    fn check_level(&self, name: &str, value: f64) -> Result<(), ReturnCode> {
+        let range = self.levels.get(name).ok_or(ReturnCode::EINVAL)?;
+        match range.contains(&value) {
+            true => Ok(()),
+            false => Err(ReturnCode::EINVAL),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockRig {
+    pub model: RigModel,
+    pub info: String,
+    pub vfo: VFO,
+    pub vfos: HashMap<VFO, VfoState>,
+    pub split: bool,
+    pub tx_vfo: VFO,
+    pub ptt: PTT,
+    pub levels: BTreeMap<String, f64>,
+    pub cache_timeout: u64,
+}
+
+impl Default for MockRig {
+    fn default() -> Self {
+        let vfos = HashMap::from([
+            (VFO::VFOA, VfoState { frequency: 14074000, mode: Mode::USB, passband: 3000 }),
+            (VFO::VFOB, VfoState { frequency: 7074000, mode: Mode::LSB, passband: 3000 }),
+        ]);
+        let levels = BTreeMap::from([
+            ("AF".to_string(), 0.5),
+            ("RFPOWER".to_string(), 0.5),
+            ("STRENGTH".to_string(), -54.0),
+        ]);
+
+        Self {
+            model: RigModel::default(),
+            info: "MockRig".to_string(),
+            vfo: VFO::VFOA,
+            vfos,
+            split: false,
+            tx_vfo: VFO::VFOB,
+            ptt: PTT::RX,
+            levels,
+            cache_timeout: 500,
+        }
+    }
+}
+
+impl MockRig {
+    // Runs a long-form command against the simulated state and returns the
+    // labelled values of the reply
+    pub fn execute(&mut self, name: &str, args: &[&str]) -> Result<Values, ReturnCode> {
+        let (requested, args) = protocol::split_vfo(args);
+        let vfo = match requested {
+            Some(VFO::CurrVfo) | None => self.vfo,
+            Some(vfo) => vfo,
+        };
+
+        match name {
+            "get_info" => Ok(vec![("Info", self.info.clone())]),
+            "get_freq" => Ok(vec![("Frequency", self.vfo_state(vfo)?.frequency.to_string())]),
+            "set_freq" => {
+                let frequency = parse_frequency(arg(args, 0)?)?;
+                self.model.check_frequency(vfo, frequency)?;
+                self.vfo_state_mut(vfo)?.frequency = frequency;
+                Ok(vec![])
+            }
+            "get_mode" => {
+                let state = self.vfo_state(vfo)?;
+                Ok(vec![("Mode", state.mode.to_string()), ("Passband", state.passband.to_string())])
+            }
+            "set_mode" => {
+                self.set_mode(vfo, args)?;
+                Ok(vec![])
+            }
+            "get_vfo" => Ok(vec![("VFO", self.vfo.to_string())]),
+            "set_vfo" => {
+                let target = requested.ok_or(ReturnCode::EINVAL)?;
+                self.vfo_state(target)?;
+                self.vfo = target;
+                Ok(vec![])
+            }
+            "get_split_vfo" => Ok(vec![("Split", (self.split as u8).to_string()), ("TX VFO", self.tx_vfo.to_string())]),
+            "set_split_vfo" => {
+                let split = parse_flag(arg(args, 0)?)?;
+                let tx_vfo = parse_vfo(arg(args, 1)?)?;
+                if split && !self.model.split {
+                    return Err(ReturnCode::ENAVAIL);
+                }
+                self.vfo_state(tx_vfo)?;
+                self.split = split;
+                self.tx_vfo = tx_vfo;
+                Ok(vec![])
+            }
+            "get_split_freq" => Ok(vec![("TX Frequency", self.vfo_state(self.tx_vfo)?.frequency.to_string())]),
+            "set_split_freq" => {
+                if !self.model.split {
+                    return Err(ReturnCode::ENAVAIL);
+                }
+                let frequency = parse_frequency(arg(args, 0)?)?;
+                self.model.check_frequency(self.tx_vfo, frequency)?;
+                self.vfo_state_mut(self.tx_vfo)?.frequency = frequency;
+                Ok(vec![])
+            }
+            "get_split_mode" => {
+                let state = self.vfo_state(self.tx_vfo)?;
+                Ok(vec![("TX Mode", state.mode.to_string()), ("TX Passband", state.passband.to_string())])
+            }
+            "set_split_mode" => {
+                if !self.model.split {
+                    return Err(ReturnCode::ENAVAIL);
+                }
+                self.set_mode(self.tx_vfo, args)?;
+                Ok(vec![])
+            }
+            "get_ptt" => Ok(vec![("PTT", self.ptt.to_string())]),
+            "set_ptt" => {
+                self.ptt = parse_arg(arg(args, 0)?)?;
+                Ok(vec![])
+            }
+            "get_level" => {
+                let level = self.levels.get(arg(args, 0)?).ok_or(ReturnCode::EINVAL)?;
+                Ok(vec![("Level Value", level.to_string())])
+            }
+            "set_level" => {
+                let name = arg(args, 0)?;
+                let value = parse_arg::<f64>(arg(args, 1)?)?;
+                self.model.check_level(name, value)?;
+                *self.levels.get_mut(name).ok_or(ReturnCode::EINVAL)? = value;
+                Ok(vec![])
+            }
+            "get_cache" => Ok(vec![("Timeout", self.cache_timeout.to_string())]),
+            "set_cache" => {
+                self.cache_timeout = parse_arg(arg(args, 0)?)?;
+                Ok(vec![])
+            }
+            _ => Err(ReturnCode::ENIMPL),
+        }
+    }
+
+    fn set_mode(&mut self, vfo: VFO, args: &[&str]) -> Result<(), ReturnCode> {
+        let mode = parse_arg(arg(args, 0)?)?;
+        let passband = args.get(1).map(|value| parse_arg::<i64>(value)).transpose()?.unwrap_or(-1);
+        let current = self.vfo_state(vfo)?.passband;
+        let passband = self.model.check_passband(mode, passband, current)?;
+
+        let state = self.vfo_state_mut(vfo)?;
+        state.mode = mode;
+        state.passband = passband;
+        Ok(())
+    }
+
+    fn vfo_state(&self, vfo: VFO) -> Result<&VfoState, ReturnCode> {
+        self.vfos.get(&vfo).ok_or(ReturnCode::EINVAL)
+    }
+
+    fn vfo_state_mut(&mut self, vfo: VFO) -> Result<&mut VfoState, ReturnCode> {
+        self.vfos.get_mut(&vfo).ok_or(ReturnCode::EINVAL)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Faults {
+    pub delay: Option<Duration>,
+    pub chunk_size: Option<usize>,
+    pub disconnect_after: Option<usize>,
+    pub errors: HashMap<String, ReturnCode>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    rig: MockRig,
+    faults: Faults,
+}
+
+// A rigctld stand-in listening on a random local port; every connection
+// shares the same simulated rig, and faults can be changed while clients
+// are connected
+pub struct MockServer {
+    address: SocketAddr,
+    shared: Arc<Mutex<Shared>>,
+    task: JoinHandle<()>,
+}
+
+impl MockServer {
+    pub async fn start() -> io::Result<Self> {
+        Self::with_rig(MockRig::default()).await
+    }
+
+    pub async fn with_rig(rig: MockRig) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        let shared = Arc::new(Mutex::new(Shared { rig, faults: Faults::default() }));
+        let task = tokio::spawn(accept(listener, shared.clone()));
+
+        Ok(Self {
+            address,
+            shared,
+            task,
+        })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn port(&self) -> u16 {
+        self.address.port()
+    }
+
+    pub fn client(&self) -> RigCtlClient {
+        RigCtlClient::new(&self.address.ip().to_string(), self.address.port(), None)
+    }
+
+    pub fn rig(&self) -> MockRig {
+        self.lock().rig.clone()
+    }
+
+    pub fn update_rig<F: FnOnce(&mut MockRig)>(&self, update: F) {
+        update(&mut self.lock().rig);
+    }
+
+    pub fn set_delay(&self, delay: Option<Duration>) {
+        self.lock().faults.delay = delay;
+    }
+
+    // Splits every reply into writes of at most `chunk_size` bytes
+    pub fn set_partial_writes(&self, chunk_size: Option<usize>) {
+        self.lock().faults.chunk_size = chunk_size.map(|size| size.max(1));
+    }
+
+    // Closes the connection instead of answering once `commands` more
+    // commands have been served
+    pub fn disconnect_after(&self, commands: Option<usize>) {
+        self.lock().faults.disconnect_after = commands;
+    }
+
+    pub fn inject_error(&self, command: &str, code: ReturnCode) {
+        self.lock().faults.errors.insert(command.to_string(), code);
+    }
+
+    pub fn clear_error(&self, command: &str) {
+        self.lock().faults.errors.remove(command);
+    }
+
+    pub fn clear_faults(&self) {
+        self.lock().faults = Faults::default();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Shared> {
+        self.shared.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn accept(listener: TcpListener, shared: Arc<Mutex<Shared>>) {
+    let mut connections = JoinSet::new();
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                log::debug!("Mock rigctld accepted connection from {}", peer);
+                connections.spawn(serve(stream, shared.clone()));
+            }
+            Err(e) => {
+                log::warn!("Mock rigctld accept failed: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+async fn serve(stream: TcpStream, shared: Arc<Mutex<Shared>>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        let request = match protocol::decode_request(&line) {
+            Some(request) => request,
+            None => continue,
+        };
+        let args = request.args.iter().map(|arg| arg.as_str()).collect::<Vec<&str>>();
+
+        let (reply, delay, chunk_size) = {
+            let mut shared = shared.lock().unwrap_or_else(|e| e.into_inner());
+            let faults = &mut shared.faults;
+
+            match faults.disconnect_after {
+                Some(0) => {
+                    faults.disconnect_after = None;
+                    log::debug!("Mock rigctld dropping connection");
+                    return;
+                }
+                Some(remaining) => faults.disconnect_after = Some(remaining - 1),
+                None => {}
+            }
+
+            let result = match faults.errors.get(&request.command) {
+                Some(code) => Err(*code),
+                None => shared.rig.execute(&request.command, &args),
+            };
+
+            (protocol::encode_reply(&request, &result), shared.faults.delay, shared.faults.chunk_size)
+        };
+
+        if let Some(delay) = delay {
+            time::sleep(delay).await;
+        }
+
+        if write_reply(&mut writer, reply.as_bytes(), chunk_size).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn write_reply<W: AsyncWriteExt + Unpin>(writer: &mut W, reply: &[u8], chunk_size: Option<usize>) -> io::Result<()> {
+    for chunk in reply.chunks(chunk_size.unwrap_or(reply.len().max(1))) {
+        writer.write_all(chunk).await?;
+        writer.flush().await?;
+        if chunk_size.is_some() {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_vfo(value: &str) -> Result<VFO, ReturnCode> {
+    parse_arg(value)
+}
+
+fn parse_flag(value: &str) -> Result<bool, ReturnCode> {
+    match value {
+        "0" => Ok(false),
+        "1" => Ok(true),
+        _ => Err(ReturnCode::EINVAL),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::ReconnectPolicy;
+    use crate::error::RigCtlError;
+
+    #[test]
+    fn test_execute() {
+        let mut rig = MockRig::default();
+        assert_eq!(rig.execute("set_freq", &["VFOB", "3573000.000000"]), Ok(vec![]));
+        assert_eq!(rig.execute("get_freq", &["VFOB"]), Ok(vec![("Frequency", "3573000".to_string())]));
+        assert_eq!(rig.execute("set_vfo", &["VFOC"]), Err(ReturnCode::EINVAL));
+        assert_eq!(rig.execute("set_vfo", &["VFOB"]), Ok(vec![]));
+        assert_eq!(rig.vfo, VFO::VFOB);
+        assert_eq!(rig.execute("get_level", &["VFOA", "STRENGTH"]), Ok(vec![("Level Value", "-54".to_string())]));
+        assert_eq!(rig.execute("vfo_op", &["UP"]), Err(ReturnCode::ENIMPL));
+    }
+
+    #[test]
+    fn test_model_constraints() {
+        let mut rig = MockRig::default();
+        assert_eq!(rig.execute("set_freq", &["VFOA", "144300000"]), Err(ReturnCode::EINVAL));
+        assert_eq!(rig.execute("set_mode", &["VFOA", "WFM", "0"]), Err(ReturnCode::EINVAL));
+        assert_eq!(rig.execute("set_mode", &["VFOA", "USB", "500"]), Err(ReturnCode::EINVAL));
+        assert_eq!(rig.execute("set_mode", &["VFOA", "CW", "0"]), Ok(vec![]));
+        assert_eq!(rig.vfos[&VFO::VFOA].passband, 500);
+        assert_eq!(rig.execute("set_level", &["VFOA", "RFPOWER", "1.5"]), Err(ReturnCode::EINVAL));
+
+        rig.model.split = false;
+        assert_eq!(rig.execute("set_split_vfo", &["VFOA", "1", "VFOB"]), Err(ReturnCode::ENAVAIL));
+        assert_eq!(rig.execute("set_split_vfo", &["VFOA", "0", "VFOB"]), Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn test_client_against_mock() {
+        let server = MockServer::start().await.unwrap();
+        let mut client = server.client();
+        client.connect().await.unwrap();
+
+        assert_eq!(client.get_freq(VFO::VFOA).await.unwrap().frequency, 14074000);
+        client.set_ptt(VFO::VFOA, PTT::TX).await.unwrap();
+        assert_eq!(server.rig().ptt, PTT::TX);
+
+        server.inject_error("get_mode", ReturnCode::ETIMEOUT);
+        server.set_partial_writes(Some(3));
+        assert!(matches!(client.get_mode(VFO::VFOA).await, Err(RigCtlError::HamlibError(ReturnCode::ETIMEOUT))));
+        assert_eq!(client.get_vfo().await.unwrap().vfo, VFO::VFOA);
+
+        server.update_rig(|rig| { rig.model.frequency_ranges.insert(VFO::VFOB, vec![7000000..=7200000]); });
+        let reply = client.execute_raw("set_freq", &["VFOB", "14074000"]).await.unwrap();
+        assert_eq!(reply.code, ReturnCode::EINVAL.code());
+
+        server.disconnect_after(Some(0));
+        assert!(matches!(client.get_info().await, Err(RigCtlError::ConnectionError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_after_timeout() {
+        let server = MockServer::start().await.unwrap();
+        let mut client = RigCtlClient::builder("127.0.0.1")
+            .port(server.port())
+            .command_timeout(Duration::from_millis(100))
+            .reconnect_policy(ReconnectPolicy { initial_backoff: Duration::from_millis(10), ..ReconnectPolicy::default() })
+            .build();
+        client.connect().await.unwrap();
+
+        server.set_delay(Some(Duration::from_millis(300)));
+        assert!(matches!(client.get_freq(VFO::VFOA).await, Err(RigCtlError::CommunicationTimeout)));
+        assert!(!client.is_connected());
+
+        server.set_delay(None);
+        assert_eq!(client.get_mode(VFO::VFOA).await.unwrap().mode, Mode::USB);
+        assert!(client.is_connected());
+    }
+}
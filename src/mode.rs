@@ -20,7 +20,7 @@ use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::str::FromStr;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Mode {
     USB,
     LSB,
@@ -44,6 +44,13 @@ pub enum Mode {
     DSB,
 }
 
+impl Mode {
+    pub const ALL: [Mode; 20] = [
+        Mode::USB, Mode::LSB, Mode::CW, Mode::CWR, Mode::RTTY, Mode::RTTYR, Mode::AM, Mode::FM, Mode::WFM, Mode::AMS,
+        Mode::PKTLSB, Mode::PKTUSB, Mode::PKTFM, Mode::ECSSUSB, Mode::ECSSLSB, Mode::FAX, Mode::SAM, Mode::SAL, Mode::SAH, Mode::DSB,
+    ];
+}
+
 impl Display for Mode {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match self {
@@ -0,0 +1,548 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use crate::error::RigCtlError;
+use crate::event::RigEvent;
+use crate::mode::Mode;
+use crate::protocol;
+use crate::protocol::{Command, ProtocolMode};
+use crate::ptt::PTT;
+use crate::vfo::VFO;
+use serde::Deserialize;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio::time;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+
+pub const DEFAULT_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 1);
+pub const DEFAULT_PORT: u16 = 4532;
+pub const DEFAULT_COMMAND_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 2);
+pub const DEFAULT_COMMAND_PORT: u16 = 4532;
+
+const MAX_PACKET_SIZE: usize = 65536;
+const DEFAULT_CAPACITY: usize = 64;
+const MIN_RECEIVE_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_RECEIVE_BACKOFF: Duration = Duration::from_secs(5);
+
+// Matches the rigctld --multicast-addr/--multicast-port and
+// --multicast-cmd-addr/--multicast-cmd-port defaults
+#[derive(Debug, Clone)]
+pub struct MulticastConfig {
+    pub group: Ipv4Addr,
+    pub port: u16,
+    pub interface: Ipv4Addr,
+    pub command_group: Ipv4Addr,
+    pub command_port: u16,
+    pub capacity: usize,
+}
+
+impl Default for MulticastConfig {
+    fn default() -> Self {
+        Self {
+            group: DEFAULT_GROUP,
+            port: DEFAULT_PORT,
+            interface: Ipv4Addr::UNSPECIFIED,
+            command_group: DEFAULT_COMMAND_GROUP,
+            command_port: DEFAULT_COMMAND_PORT,
+            capacity: DEFAULT_CAPACITY,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RigState {
+    pub seq: u64,
+    pub time: String,
+    pub rig: RigInfo,
+    pub vfos: Vec<VfoState>,
+    pub spectra: Vec<Spectrum>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RigInfo {
+    pub model: String,
+    pub endpoint: String,
+    pub process: String,
+    pub name: String,
+    pub status: String,
+    pub error: Option<String>,
+    pub split: bool,
+    pub split_vfo: Option<VFO>,
+    pub sat_mode: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VfoState {
+    // None for VFOs this crate has no name for, such as the MainA/SubA
+    // pairs of satellite rigs; the name sent by Hamlib is kept in any case
+    pub vfo: Option<VFO>,
+    pub name: String,
+    pub frequency: u64,
+    // None when the mode is unknown to Hamlib or to this crate
+    pub mode: Option<Mode>,
+    pub passband: u64,
+    pub ptt: bool,
+    pub rx: bool,
+    pub tx: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spectrum {
+    pub id: u32,
+    pub name: String,
+    pub kind: String,
+    pub min_level: i32,
+    pub max_level: i32,
+    pub min_strength: i32,
+    pub max_strength: i32,
+    pub center_frequency: u64,
+    pub span: u64,
+    pub low_frequency: u64,
+    pub high_frequency: u64,
+    pub data: Vec<u8>,
+}
+
+// Wire format, as serialized by Hamlib; converted to the public types above
+#[derive(Deserialize)]
+struct Packet {
+    #[serde(default)]
+    seq: u64,
+    #[serde(default)]
+    time: String,
+    rig: PacketRig,
+    #[serde(default)]
+    vfos: Vec<PacketVfo>,
+    #[serde(default)]
+    spectra: Vec<PacketSpectrum>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PacketRig {
+    #[serde(default)]
+    id: PacketRigId,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    error_msg: String,
+    #[serde(default)]
+    split: bool,
+    #[serde(default)]
+    split_vfo: String,
+    #[serde(default)]
+    sat_mode: bool,
+}
+
+#[derive(Deserialize, Default)]
+struct PacketRigId {
+    #[serde(default)]
+    model: String,
+    #[serde(default)]
+    endpoint: String,
+    #[serde(default)]
+    process: String,
+}
+
+#[derive(Deserialize)]
+struct PacketVfo {
+    name: String,
+    freq: f64,
+    #[serde(default)]
+    mode: String,
+    #[serde(default)]
+    width: f64,
+    #[serde(default)]
+    ptt: bool,
+    #[serde(default)]
+    rx: bool,
+    #[serde(default)]
+    tx: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PacketSpectrum {
+    id: u32,
+    #[serde(default)]
+    name: String,
+    #[serde(default, rename = "type")]
+    kind: String,
+    #[serde(default)]
+    min_level: i32,
+    #[serde(default)]
+    max_level: i32,
+    #[serde(default)]
+    min_strength: i32,
+    #[serde(default)]
+    max_strength: i32,
+    #[serde(default)]
+    center_freq: f64,
+    #[serde(default)]
+    span: f64,
+    #[serde(default)]
+    low_freq: f64,
+    #[serde(default)]
+    high_freq: f64,
+    // Hex encoded, one byte per bin
+    #[serde(default)]
+    data: String,
+}
+
+pub fn parse(data: &[u8]) -> Result<RigState, RigCtlError> {
+    let packet: Packet = serde_json::from_slice(data).map_err(|e| RigCtlError::ResponseParsing(e.to_string()))?;
+
+    let vfos = packet.vfos.into_iter()
+        .map(|vfo| VfoState {
+            vfo: VFO::from_str(&vfo.name).ok(),
+            name: vfo.name,
+            frequency: vfo.freq.round() as u64,
+            mode: Mode::from_str(&vfo.mode).ok(),
+            passband: vfo.width.max(0.0).round() as u64,
+            ptt: vfo.ptt,
+            rx: vfo.rx,
+            tx: vfo.tx,
+        })
+        .collect::<Vec<VfoState>>();
+
+    let spectra = packet.spectra.into_iter()
+        .map(|spectrum| Ok(Spectrum {
+            id: spectrum.id,
+            name: spectrum.name,
+            kind: spectrum.kind,
+            min_level: spectrum.min_level,
+            max_level: spectrum.max_level,
+            min_strength: spectrum.min_strength,
+            max_strength: spectrum.max_strength,
+            center_frequency: spectrum.center_freq.round() as u64,
+            span: spectrum.span.round() as u64,
+            low_frequency: spectrum.low_freq.round() as u64,
+            high_frequency: spectrum.high_freq.round() as u64,
+            data: decode_hex(&spectrum.data)?,
+        }))
+        .collect::<Result<Vec<Spectrum>, RigCtlError>>()?;
+
+    let rig = packet.rig;
+    Ok(RigState {
+        seq: packet.seq,
+        time: packet.time,
+        rig: RigInfo {
+            model: rig.id.model,
+            endpoint: rig.id.endpoint,
+            process: rig.id.process,
+            name: rig.name,
+            status: rig.status,
+            error: Some(rig.error_msg).filter(|message| !message.is_empty()),
+            split: rig.split,
+            split_vfo: VFO::from_str(&rig.split_vfo).ok(),
+            sat_mode: rig.sat_mode,
+        },
+        vfos,
+        spectra,
+    })
+}
+
+impl RigState {
+    // The VFO being received on, or the first one listed
+    pub fn rx_vfo(&self) -> Option<&VfoState> {
+        self.vfos.iter().find(|vfo| vfo.rx).or(self.vfos.first())
+    }
+
+    pub fn ptt(&self) -> PTT {
+        match self.vfos.iter().any(|vfo| vfo.ptt) {
+            true => PTT::TX,
+            false => PTT::RX,
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        matches!(self.rig.status.as_str(), "" | "OK" | "WARNING")
+    }
+}
+
+// Changes between two successive states; without a previous state (or after
+// a disconnection) the whole current state is reported
+pub fn changes(previous: Option<&RigState>, current: &RigState) -> Vec<RigEvent> {
+    let previous = previous.filter(|previous| previous.is_connected());
+    if !current.is_connected() {
+        return match previous {
+            Some(_) => vec![RigEvent::Disconnected],
+            None => Vec::new(),
+        };
+    }
+
+    let mut events = Vec::new();
+    let before = previous.and_then(RigState::rx_vfo);
+
+    if let Some(vfo) = current.rx_vfo() {
+        if before.map(|before| before.frequency) != Some(vfo.frequency) {
+            events.push(RigEvent::FrequencyChanged(vfo.frequency));
+        }
+        if let Some(mode) = vfo.mode {
+            if before.map(|before| (before.mode, before.passband)) != Some((Some(mode), vfo.passband)) {
+                events.push(RigEvent::ModeChanged { mode, passband: vfo.passband });
+            }
+        }
+        if let Some(name) = vfo.vfo {
+            if before.and_then(|before| before.vfo) != Some(name) {
+                events.push(RigEvent::VfoChanged(name));
+            }
+        }
+    }
+
+    if let Some(tx_vfo) = current.rig.split_vfo {
+        if previous.map(|previous| (previous.rig.split, previous.rig.split_vfo)) != Some((current.rig.split, Some(tx_vfo))) {
+            events.push(RigEvent::SplitChanged { split: current.rig.split, tx_vfo });
+        }
+    }
+
+    if previous.map(RigState::ptt) != Some(current.ptt()) {
+        events.push(RigEvent::PttChanged(current.ptt()));
+    }
+
+    events
+}
+
+fn decode_hex(data: &str) -> Result<Vec<u8>, RigCtlError> {
+    if !data.len().is_multiple_of(2) {
+        return Err(RigCtlError::ResponseParsing("Odd length spectrum data".to_string()));
+    }
+
+    (0..data.len())
+        .step_by(2)
+        .map(|index| {
+            data.get(index..index + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or(RigCtlError::ResponseParsing("Invalid spectrum data".to_string()))
+        })
+        .collect()
+}
+
+// Joins the state group and publishes every valid packet; invalid ones are
+// logged and dropped
+pub struct MulticastListener {
+    config: MulticastConfig,
+    local_addr: SocketAddr,
+    sender: UdpSocket,
+    states: broadcast::Sender<RigState>,
+    events: broadcast::Sender<RigEvent>,
+    task: JoinHandle<()>,
+}
+
+impl MulticastListener {
+    pub async fn bind(config: MulticastConfig) -> Result<Self, RigCtlError> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_address(true)?;
+        socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, config.port).into())?;
+        socket.join_multicast_v4(&config.group, &config.interface)?;
+        socket.set_nonblocking(true)?;
+
+        let socket = UdpSocket::from_std(socket.into())?;
+        let local_addr = socket.local_addr()?;
+
+        let sender = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+        sender.set_multicast_loop_v4(true)?;
+        if !config.interface.is_unspecified() {
+            let sender = socket2::SockRef::from(&sender);
+            sender.set_multicast_if_v4(&config.interface)?;
+        }
+
+        let (states, _) = broadcast::channel(config.capacity.max(1));
+        let (events, _) = broadcast::channel(config.capacity.max(1));
+        let task = tokio::spawn(receive(socket, states.clone(), events.clone()));
+
+        Ok(Self { config, local_addr, sender, states, events, task })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<RigState> {
+        self.states.subscribe()
+    }
+
+    // States missed by a lagging consumer are skipped
+    pub fn stream(&self) -> impl Stream<Item = RigState> {
+        BroadcastStream::new(self.states.subscribe()).filter_map(Result::ok)
+    }
+
+    // Changes between successive state packets, see `changes`
+    pub fn subscribe_events(&self) -> broadcast::Receiver<RigEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn events(&self) -> impl Stream<Item = RigEvent> {
+        BroadcastStream::new(self.events.subscribe()).filter_map(Result::ok)
+    }
+
+    // Commands go to the command group, where rigctld executes them; no
+    // reply is sent back, the effect shows up in the next state packets
+    pub async fn send_command(&self, command: &Command) -> Result<(), RigCtlError> {
+        self.send_line(&command.to_string()).await
+    }
+
+    pub async fn send_line(&self, command: &str) -> Result<(), RigCtlError> {
+        let data = protocol::encode(ProtocolMode::Default, &[command.to_string()]);
+        log::trace!(" >>> [{}] ({} bytes)", protocol::redact(String::from_utf8_lossy(&data).trim_end()), data.len());
+        self.sender.send_to(&data, (self.config.command_group, self.config.command_port)).await?;
+        Ok(())
+    }
+}
+
+impl Drop for MulticastListener {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+// Receive errors are retried with a growing delay, so that a persistent
+// failure neither spins nor floods the log
+async fn receive(socket: UdpSocket, states: broadcast::Sender<RigState>, events: broadcast::Sender<RigEvent>) {
+    let mut buf = vec![0u8; MAX_PACKET_SIZE];
+    let mut backoff = MIN_RECEIVE_BACKOFF;
+    let mut previous: Option<RigState> = None;
+
+    loop {
+        let (size, source) = match socket.recv_from(&mut buf).await {
+            Ok(received) => {
+                backoff = MIN_RECEIVE_BACKOFF;
+                received
+            }
+            Err(e) => {
+                log::warn!("Multicast receive failed, retrying in {} ms: {}", backoff.as_millis(), e);
+                time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_RECEIVE_BACKOFF);
+                continue;
+            }
+        };
+
+        match parse(&buf[..size]) {
+            Ok(state) => {
+                // Spectrum packets carry no VFOs and say nothing about changes
+                if !state.vfos.is_empty() || !state.is_connected() {
+                    for event in changes(previous.as_ref(), &state) {
+                        let _ = events.send(event);
+                    }
+                    previous = Some(state.clone());
+                }
+                let _ = states.send(state);
+            }
+            Err(e) => log::debug!("Dropping invalid packet from {}: {}", source, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PACKET: &str = r#"{
+        "app": "Hamlib", "version": "4.6", "seq": 42, "time": "2024-05-01T10:00:00.000000+0000", "crc": 0,
+        "rig": {
+            "id": {"model": "IC-7300", "endpoint": "/dev/ttyUSB0", "process": "1234"},
+            "status": "OK", "errorMsg": "", "name": "IC-7300",
+            "split": true, "splitVfo": "VFOB", "satMode": false
+        },
+        "vfos": [
+            {"name": "VFOA", "freq": 14074000.0, "mode": "PKTUSB", "width": 3000, "ptt": false, "rx": true, "tx": false},
+            {"name": "VFOB", "freq": 14076000.0, "mode": "USB", "width": 2400, "ptt": false, "rx": false, "tx": true}
+        ],
+        "spectra": [
+            {"id": 0, "name": "Main", "type": "CENTER", "minLevel": 0, "maxLevel": 160, "minStrength": -100, "maxStrength": 0,
+             "centerFreq": 14074000, "span": 25000, "lowFreq": 14061500, "highFreq": 14086500, "length": 4, "data": "00107FA0"}
+        ]
+    }"#;
+
+    #[test]
+    fn test_parse() {
+        let state = parse(PACKET.as_bytes()).unwrap();
+
+        assert_eq!(state.seq, 42);
+        assert_eq!(state.rig.name, "IC-7300");
+        assert_eq!(state.rig.error, None);
+        assert!(state.rig.split);
+        assert_eq!(state.rig.split_vfo, Some(VFO::VFOB));
+        assert_eq!(state.vfos[0], VfoState { vfo: Some(VFO::VFOA), name: "VFOA".to_string(), frequency: 14074000, mode: Some(Mode::PKTUSB), passband: 3000, ptt: false, rx: true, tx: false });
+        assert!(state.vfos[1].tx);
+        assert_eq!(state.spectra[0].kind, "CENTER");
+        assert_eq!(state.spectra[0].data, vec![0x00, 0x10, 0x7f, 0xa0]);
+    }
+
+    #[test]
+    fn test_parse_unknown_vfo() {
+        let state = parse(br#"{"rig": {}, "vfos": [{"name": "MainA", "freq": 145800000}, {"name": "VFOB", "freq": 1}]}"#).unwrap();
+        assert_eq!(state.vfos[0].vfo, None);
+        assert_eq!(state.vfos[0].name, "MainA");
+        assert_eq!(state.vfos[1].vfo, Some(VFO::VFOB));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse(b"not json").is_err());
+        assert!(parse(br#"{"rig": {}, "spectra": [{"id": 0, "data": "0"}]}"#).is_err());
+    }
+
+    #[test]
+    fn test_changes() {
+        let state = parse(PACKET.as_bytes()).unwrap();
+        let initial = changes(None, &state);
+        assert!(initial.contains(&RigEvent::FrequencyChanged(14074000)));
+        assert!(initial.contains(&RigEvent::ModeChanged { mode: Mode::PKTUSB, passband: 3000 }));
+        assert!(initial.contains(&RigEvent::SplitChanged { split: true, tx_vfo: VFO::VFOB }));
+        assert!(initial.contains(&RigEvent::PttChanged(PTT::RX)));
+        assert!(changes(Some(&state), &state).is_empty());
+
+        let mut next = state.clone();
+        next.vfos[0].frequency = 14075000;
+        next.vfos[1].ptt = true;
+        assert_eq!(changes(Some(&state), &next), vec![RigEvent::FrequencyChanged(14075000), RigEvent::PttChanged(PTT::TX)]);
+
+        let mut lost = next.clone();
+        lost.rig.status = "DISCONNECTED".to_string();
+        assert_eq!(changes(Some(&next), &lost), vec![RigEvent::Disconnected]);
+        assert_eq!(changes(Some(&lost), &next).len(), initial.len());
+    }
+
+    // Goes through the group join on the loopback interface, which needs no
+    // multicast route
+    #[tokio::test]
+    async fn test_listener_publishes_states() {
+        let group = Ipv4Addr::new(239, 255, 45, 32);
+        let config = MulticastConfig { group, port: 0, interface: Ipv4Addr::LOCALHOST, ..MulticastConfig::default() };
+        let listener = MulticastListener::bind(config).await.unwrap();
+        let mut states = listener.subscribe();
+        let mut events = listener.subscribe_events();
+
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        socket.set_multicast_loop_v4(true).unwrap();
+        let target = (group, listener.local_addr().port());
+        socket.send_to(b"garbage", target).await.unwrap();
+        socket.send_to(PACKET.as_bytes(), target).await.unwrap();
+
+        let state = tokio::time::timeout(Duration::from_secs(2), states.recv()).await.unwrap().unwrap();
+        assert_eq!(state.seq, 42);
+        assert_eq!(events.recv().await.unwrap(), RigEvent::FrequencyChanged(14074000));
+    }
+}
@@ -0,0 +1,685 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use crate::commands;
+use crate::commands::{get_cache, get_freq, get_info, get_level, get_mode, get_ptt, get_rit, get_xit, get_split_freq, get_split_mode, get_split_vfo, get_vfo, set_cache, set_freq, set_mode, set_ptt, set_trn};
+use crate::error::{ReturnCode, RigCtlError};
+use crate::event;
+use crate::mode::Mode;
+use crate::ptt::PTT;
+use crate::vfo::VFO;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Command {
+    GetInfo,
+    GetMode(VFO),
+    GetFreq(VFO),
+    GetVfo,
+    GetSplitVfo,
+    GetSplitMode(VFO),
+    GetSplitFreq(VFO),
+    SetPtt(VFO, PTT),
+    GetPtt(VFO),
+    SetFreq(VFO, u64),
+    // A passband of 0 selects the rig default for the mode
+    SetMode(VFO, Mode, u64),
+    GetLevel(VFO, &'static str),
+    GetRit(VFO),
+    GetXit(VFO),
+    SetTrn(bool),
+    GetCache,
+    SetCache(u64),
+}
+
+impl Command {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::GetInfo => "get_info",
+            Command::GetMode(_) => "get_mode",
+            Command::GetFreq(_) => "get_freq",
+            Command::GetVfo => "get_vfo",
+            Command::GetSplitVfo => "get_split_vfo",
+            Command::GetSplitMode(_) => "get_split_mode",
+            Command::GetSplitFreq(_) => "get_split_freq",
+            Command::SetPtt(_, _) => "set_ptt",
+            Command::GetPtt(_) => "get_ptt",
+            Command::SetFreq(_, _) => "set_freq",
+            Command::SetMode(_, _, _) => "set_mode",
+            Command::GetLevel(_, _) => "get_level",
+            Command::GetRit(_) => "get_rit",
+            Command::GetXit(_) => "get_xit",
+            Command::SetTrn(_) => "set_trn",
+            Command::GetCache => "get_cache",
+            Command::SetCache(_) => "set_cache",
+        }
+    }
+
+    pub fn parse_response(&self, line: &str) -> Result<Response, RigCtlError> {
+        commands::check_return_code(line)?;
+
+        match self {
+            Command::GetInfo => get_info::parse(line).map(Response::Info),
+            Command::GetMode(_) => get_mode::parse(line).map(Response::Mode),
+            Command::GetFreq(_) => get_freq::parse(line).map(Response::Freq),
+            Command::GetVfo => get_vfo::parse(line).map(Response::Vfo),
+            Command::GetSplitVfo => get_split_vfo::parse(line).map(Response::SplitVfo),
+            Command::GetSplitMode(_) => get_split_mode::parse(line).map(Response::SplitMode),
+            Command::GetSplitFreq(_) => get_split_freq::parse(line).map(Response::SplitFreq),
+            Command::SetPtt(_, _) => set_ptt::parse(line).map(|_| Response::Ptt),
+            Command::GetPtt(_) => get_ptt::parse(line).map(Response::PttState),
+            Command::SetFreq(_, _) => set_freq::parse(line).map(|_| Response::SetFreq),
+            Command::SetMode(_, _, _) => set_mode::parse(line).map(|_| Response::SetMode),
+            Command::GetLevel(_, _) => get_level::parse(line).map(Response::Level),
+            Command::GetRit(_) => get_rit::parse(line).map(Response::Rit),
+            Command::GetXit(_) => get_xit::parse(line).map(Response::Xit),
+            Command::SetTrn(_) => set_trn::parse(line).map(|_| Response::SetTrn),
+            Command::GetCache => get_cache::parse(line).map(Response::Cache),
+            Command::SetCache(_) => set_cache::parse(line).map(|_| Response::SetCache),
+        }
+    }
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::GetInfo => { write!(f, "get_info") }
+            Command::GetMode(vfo) => { write!(f, "get_mode {}", vfo) }
+            Command::GetFreq(vfo) => { write!(f, "get_freq {}", vfo) }
+            Command::GetVfo => { write!(f, "get_vfo") }
+            Command::GetSplitVfo => { write!(f, "get_split_vfo 0") }
+            Command::GetSplitMode(vfo) => { write!(f, "get_split_mode {}", vfo) }
+            Command::GetSplitFreq(vfo) => { write!(f, "get_split_freq {}", vfo) }
+            Command::SetPtt(vfo, ptt) => { write!(f, "set_ptt {} {}", vfo, ptt) }
+            Command::GetPtt(vfo) => { write!(f, "get_ptt {}", vfo) }
+            Command::SetFreq(vfo, frequency) => { write!(f, "set_freq {} {}", vfo, frequency) }
+            Command::SetMode(vfo, mode, passband) => { write!(f, "set_mode {} {} {}", vfo, mode, passband) }
+            Command::GetLevel(vfo, level) => { write!(f, "get_level {} {}", vfo, level) }
+            Command::GetRit(vfo) => { write!(f, "get_rit {}", vfo) }
+            Command::GetXit(vfo) => { write!(f, "get_xit {}", vfo) }
+            Command::SetTrn(enabled) => { write!(f, "set_trn {}", if *enabled { "RIG" } else { "OFF" }) }
+            Command::GetCache => { write!(f, "get_cache") }
+            Command::SetCache(timeout) => { write!(f, "set_cache {}", timeout) }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Response {
+    Info(get_info::Response),
+    Mode(get_mode::Response),
+    Freq(get_freq::Response),
+    Vfo(get_vfo::Response),
+    SplitVfo(get_split_vfo::Response),
+    SplitMode(get_split_mode::Response),
+    SplitFreq(get_split_freq::Response),
+    Ptt,
+    PttState(get_ptt::Response),
+    SetFreq,
+    SetMode,
+    Level(get_level::Response),
+    Rit(get_rit::Response),
+    Xit(get_xit::Response),
+    SetTrn,
+    Cache(get_cache::Response),
+    SetCache,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ProtocolMode {
+    Default,
+    ExtendedNewline,
+    #[default]
+    ExtendedPipe,
+}
+
+impl ProtocolMode {
+    fn prefix(&self) -> &'static [u8] {
+        match self {
+            ProtocolMode::Default => b"\\",
+            ProtocolMode::ExtendedNewline => b"+\\",
+            ProtocolMode::ExtendedPipe => b"|\\",
+        }
+    }
+}
+
+impl Display for ProtocolMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolMode::Default => { write!(f, "Default") }
+            ProtocolMode::ExtendedNewline => { write!(f, "Extended (newline)") }
+            ProtocolMode::ExtendedPipe => { write!(f, "Extended (pipe)") }
+        }
+    }
+}
+
+pub fn encode(mode: ProtocolMode, commands: &[String]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for command in commands {
+        data.extend_from_slice(mode.prefix());
+        data.extend_from_slice(command.as_bytes());
+        data.push(b'\n');
+    }
+    data
+}
+
+// Labels of the values rigctld prints, one per line, for each command in
+// default mode; used to rebuild the extended reply the parsers expect
+fn value_labels(name: &str) -> &'static [&'static str] {
+    match name {
+        "get_info" => &["Info"],
+        "get_mode" => &["Mode", "Passband"],
+        "get_freq" => &["Frequency"],
+        "get_vfo" => &["VFO"],
+        "get_split_vfo" => &["Split", "TX VFO"],
+        "get_split_mode" => &["TX Mode", "TX Passband"],
+        "get_split_freq" => &["TX Frequency"],
+        "get_ptt" => &["PTT"],
+        "get_level" => &["Level Value"],
+        "get_rit" => &["RIT"],
+        "get_xit" => &["XIT"],
+        "get_cache" => &["Timeout"],
+        name if name.starts_with("set_") => &[],
+        _ => &["Value"],
+    }
+}
+
+// Masks credentials in outgoing commands and their echo, for logging
+pub fn redact(text: &str) -> String {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"(password:? )[^|\n]*").unwrap();
+    }
+
+    RE.replace_all(text, "${1}***").to_string()
+}
+
+// A command line as received by a rigctld-compatible server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub mode: ProtocolMode,
+    pub command: String,
+    pub args: Vec<String>,
+}
+
+pub type Values = Vec<(&'static str, String)>;
+
+// Parses a request in any protocol mode, long (`\get_freq`) or short (`f`)
+// form; short commands are translated to their long name
+pub fn decode_request(line: &str) -> Option<Request> {
+    let line = line.trim();
+    let (mode, line) = match line.chars().next()? {
+        '+' => (ProtocolMode::ExtendedNewline, &line[1..]),
+        '|' => (ProtocolMode::ExtendedPipe, &line[1..]),
+        _ => (ProtocolMode::Default, line),
+    };
+
+    let (command, rest) = match line.strip_prefix('\\') {
+        Some(line) => {
+            let mut parts = line.splitn(2, char::is_whitespace);
+            (parts.next()?.to_string(), parts.next().unwrap_or_default())
+        }
+        None => {
+            let short = line.chars().next()?;
+            (short_command(short)?.to_string(), &line[short.len_utf8()..])
+        }
+    };
+
+    if command.is_empty() {
+        return None;
+    }
+
+    Some(Request {
+        mode,
+        command,
+        args: rest.split_whitespace().map(|arg| arg.to_string()).collect(),
+    })
+}
+
+// Formats a reply the way rigctld does for the mode of the request
+pub fn encode_reply<L: AsRef<str>>(request: &Request, result: &Result<Vec<(L, String)>, ReturnCode>) -> String {
+    let echo = echo(request);
+    let (values, code) = match result {
+        Ok(values) => (values.as_slice(), 0),
+        Err(code) => (&[][..], code.code()),
+    };
+
+    match request.mode {
+        ProtocolMode::ExtendedPipe => {
+            let mut reply = format!("{}: {}|", request.command, echo);
+            for (label, value) in values {
+                reply.push_str(&format!("{}: {}|", label.as_ref(), value));
+            }
+            reply.push_str(&format!("RPRT {}\n", code));
+            reply
+        }
+        ProtocolMode::ExtendedNewline => {
+            let mut reply = format!("{}: {}\n", request.command, echo);
+            for (label, value) in values {
+                reply.push_str(&format!("{}: {}\n", label.as_ref(), value));
+            }
+            reply.push_str(&format!("RPRT {}\n", code));
+            reply
+        }
+        ProtocolMode::Default => match values.is_empty() {
+            true => format!("RPRT {}\n", code),
+            false => values.iter().map(|(_, value)| format!("{}\n", value)).collect(),
+        },
+    }
+}
+
+// Getters that only take a VFO echo that VFO and ignore anything after it,
+// such as the 0 clients send with get_split_vfo
+fn echo(request: &Request) -> String {
+    let vfo = match split_vfo(&request.args) {
+        (Some(_), _) => request.args[0].clone(),
+        (None, _) => "currVFO".to_string(),
+    };
+
+    match request.command.as_str() {
+        "get_info" | "get_freq" | "get_mode" | "get_vfo" | "get_ptt" | "get_split_vfo" | "get_split_freq" | "get_split_mode" | "get_rit" | "get_xit" => vfo,
+        _ if request.args.is_empty() => vfo,
+        _ => request.args.join(" "),
+    }
+}
+
+// Splits the optional leading VFO argument sent by clients in VFO mode
+pub fn split_vfo<S: AsRef<str>>(args: &[S]) -> (Option<VFO>, &[S]) {
+    match args.first().map(|arg| arg.as_ref()) {
+        Some(arg) if arg.eq_ignore_ascii_case("currVFO") => (Some(VFO::CurrVfo), &args[1..]),
+        Some(arg) => match VFO::from_str(arg) {
+            Ok(vfo) => (Some(vfo), &args[1..]),
+            Err(_) => (None, args),
+        },
+        None => (None, args),
+    }
+}
+
+// Helpers for servers answering requests, failing with EINVAL as rigctld
+// does when an argument is missing or malformed
+pub fn arg<S: AsRef<str>>(args: &[S], index: usize) -> Result<&str, ReturnCode> {
+    args.get(index).map(|arg| arg.as_ref()).ok_or(ReturnCode::EINVAL)
+}
+
+pub fn parse_arg<T: FromStr>(value: &str) -> Result<T, ReturnCode> {
+    value.parse::<T>().map_err(|_| ReturnCode::EINVAL)
+}
+
+// rigctld accepts frequencies with a fractional part
+pub fn parse_frequency(value: &str) -> Result<u64, ReturnCode> {
+    let frequency = parse_arg::<f64>(value)?;
+    match frequency.is_finite() && frequency >= 0.0 {
+        true => Ok(frequency.round() as u64),
+        false => Err(ReturnCode::EINVAL),
+    }
+}
+
+fn short_command(short: char) -> Option<&'static str> {
+    match short {
+        'F' => Some("set_freq"),
+        'f' => Some("get_freq"),
+        'M' => Some("set_mode"),
+        'm' => Some("get_mode"),
+        'V' => Some("set_vfo"),
+        'v' => Some("get_vfo"),
+        'T' => Some("set_ptt"),
+        't' => Some("get_ptt"),
+        'I' => Some("set_split_freq"),
+        'i' => Some("get_split_freq"),
+        'X' => Some("set_split_mode"),
+        'x' => Some("get_split_mode"),
+        'S' => Some("set_split_vfo"),
+        's' => Some("get_split_vfo"),
+        'L' => Some("set_level"),
+        'l' => Some("get_level"),
+        'J' => Some("set_rit"),
+        'j' => Some("get_rit"),
+        'Z' => Some("set_xit"),
+        'z' => Some("get_xit"),
+        'A' => Some("set_trn"),
+        'a' => Some("get_trn"),
+        '_' => Some("get_info"),
+        'q' | 'Q' => Some("quit"),
+        _ => None,
+    }
+}
+
+const MAX_EVENTS: usize = 64;
+
+#[derive(Debug, Default)]
+pub struct Decoder {
+    mode: ProtocolMode,
+    buffer: Vec<u8>,
+    events: VecDeque<String>,
+}
+
+impl Decoder {
+    pub fn new(mode: ProtocolMode) -> Self {
+        Self {
+            mode,
+            buffer: Vec::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn mode(&self) -> ProtocolMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: ProtocolMode) {
+        self.mode = mode;
+        self.clear();
+    }
+
+    // Pushed events can show up anywhere, even between the lines of a reply;
+    // complete event lines are moved out of the buffer as soon as they are
+    // fed, so that replies are framed as if they had never been sent
+    pub fn feed(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+        self.set_events_aside();
+    }
+
+    // Returns the next complete reply to `command`, normalized to the
+    // pipe-separated extended format regardless of the protocol mode
+    pub fn next_response(&mut self, command: &str) -> Result<Option<String>, RigCtlError> {
+        self.next_framed(self.mode, command)
+    }
+
+    // Same as `next_response`, for a command sent with the prefix of `mode`
+    // instead of the decoder's own
+    pub fn next_framed(&mut self, mode: ProtocolMode, command: &str) -> Result<Option<String>, RigCtlError> {
+        let mut lines = Vec::new();
+        let mut consumed = 0;
+
+        while let Some(position) = self.buffer[consumed..].iter().position(|b| *b == b'\n') {
+            let line = std::str::from_utf8(&self.buffer[consumed..consumed + position])
+                .map_err(|e| RigCtlError::RawDataError(e.to_string()))?
+                .trim_end()
+                .to_string();
+            consumed += position + 1;
+
+            if line.is_empty() && mode != ProtocolMode::ExtendedPipe {
+                continue;
+            }
+
+            lines.push(line);
+
+            if let Some(response) = frame(mode, command, &lines) {
+                self.buffer.drain(..consumed);
+                if !answers(command, &response) {
+                    log::debug!("Discarding reply to another command: {}", redact(&response));
+                    consumed = 0;
+                    lines.clear();
+                    continue;
+                }
+                return Ok(Some(response));
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Returns the next unsolicited event line, in the order they were fed
+    pub fn next_event(&mut self) -> Option<String> {
+        self.events.pop_front()
+    }
+
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+        self.events.clear();
+    }
+
+    fn set_events_aside(&mut self) {
+        let prefix = event::EVENT_PREFIX.as_bytes();
+        if !self.buffer.windows(prefix.len()).any(|window| window == prefix) {
+            return;
+        }
+
+        let mut kept = Vec::with_capacity(self.buffer.len());
+        let mut rest = self.buffer.as_slice();
+        while let Some(position) = rest.iter().position(|b| *b == b'\n') {
+            let line = String::from_utf8_lossy(&rest[..position]);
+            match event::is_event(&line) {
+                true => self.events.push_back(line.trim_end().to_string()),
+                false => kept.extend_from_slice(&rest[..=position]),
+            }
+            rest = &rest[position + 1..];
+        }
+        kept.extend_from_slice(rest);
+        self.buffer = kept;
+
+        // Nobody may be reading them, as in the blocking client
+        while self.events.len() > MAX_EVENTS {
+            if let Some(line) = self.events.pop_front() {
+                log::debug!("Dropping unread event: {}", line);
+            }
+        }
+    }
+}
+
+// Extended replies echo the command they answer; a different echo is a
+// late reply to an earlier command
+fn answers(command: &str, reply: &str) -> bool {
+    let name = command.split_whitespace().next().unwrap_or_default();
+    match reply.split_once(':') {
+        Some((echo, _)) if !echo.contains('|') => echo == name,
+        _ => true,
+    }
+}
+
+fn frame(mode: ProtocolMode, command: &str, lines: &[String]) -> Option<String> {
+    let last = lines.last()?;
+
+    match mode {
+        ProtocolMode::ExtendedPipe => Some(last.clone()),
+        ProtocolMode::ExtendedNewline => match last.starts_with("RPRT ") {
+            true => Some(lines.join("|")),
+            false => None,
+        },
+        ProtocolMode::Default => {
+            let mut tokens = command.split_whitespace();
+            let name = tokens.next().unwrap_or_default();
+            let args = tokens.collect::<Vec<&str>>();
+            let echo = match name.starts_with("set_") {
+                true => args.join(" "),
+                false => args.first()
+                    .filter(|arg| VFO::from_str(arg).is_ok())
+                    .unwrap_or(&"currVFO")
+                    .to_string(),
+            };
+
+            if lines[0].starts_with("RPRT ") {
+                return Some(format!("{}: {}|{}", name, echo, lines[0]));
+            }
+
+            let labels = value_labels(name);
+            if lines.len() < labels.len() {
+                return None;
+            }
+
+            let values = labels.iter()
+                .zip(lines)
+                .map(|(label, value)| format!("{}: {}", label, value))
+                .collect::<Vec<String>>();
+            Some(format!("{}: {}|{}|RPRT 0", name, echo, values.join("|")))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let commands = vec![Command::GetFreq(VFO::VFOA).to_string(), Command::SetPtt(VFO::VFOA, PTT::TX).to_string()];
+        let actual = encode(ProtocolMode::ExtendedPipe, &commands);
+        assert_eq!(actual, b"|\\get_freq VFOA\n|\\set_ptt VFOA 1\n".to_vec());
+    }
+
+    #[test]
+    fn test_encode_modes() {
+        let commands = vec![Command::GetVfo.to_string()];
+        assert_eq!(encode(ProtocolMode::Default, &commands), b"\\get_vfo\n".to_vec());
+        assert_eq!(encode(ProtocolMode::ExtendedNewline, &commands), b"+\\get_vfo\n".to_vec());
+    }
+
+    #[test]
+    fn test_decode_request() {
+        let request = decode_request("|\\set_freq VFOA 14074000\n").unwrap();
+        assert_eq!(request.mode, ProtocolMode::ExtendedPipe);
+        assert_eq!(request.command, "set_freq");
+        assert_eq!(request.args, vec!["VFOA", "14074000"]);
+
+        let request = decode_request("F 7074000.000000").unwrap();
+        assert_eq!(request.mode, ProtocolMode::Default);
+        assert_eq!(request.command, "set_freq");
+        assert_eq!(request.args, vec!["7074000.000000"]);
+
+        assert_eq!(decode_request("+m").unwrap().mode, ProtocolMode::ExtendedNewline);
+        assert_eq!(decode_request("\\"), None);
+        assert_eq!(decode_request("?"), None);
+    }
+
+    #[test]
+    fn test_encode_reply() {
+        let values = Ok(vec![("Mode", "USB".to_string()), ("Passband", "3000".to_string())]);
+        let mut request = decode_request("|\\get_mode VFOA").unwrap();
+        assert_eq!(encode_reply(&request, &values), "get_mode: VFOA|Mode: USB|Passband: 3000|RPRT 0\n");
+        request.mode = ProtocolMode::ExtendedNewline;
+        assert_eq!(encode_reply(&request, &values), "get_mode: VFOA\nMode: USB\nPassband: 3000\nRPRT 0\n");
+        request.mode = ProtocolMode::Default;
+        assert_eq!(encode_reply(&request, &values), "USB\n3000\n");
+        assert_eq!(encode_reply::<&str>(&request, &Err(ReturnCode::EINVAL)), "RPRT -1\n");
+
+        let request = decode_request("|\\get_split_vfo 0").unwrap();
+        assert_eq!(encode_reply::<&str>(&request, &Ok(vec![])), "get_split_vfo: currVFO|RPRT 0\n");
+    }
+
+    #[test]
+    fn test_split_vfo() {
+        assert_eq!(split_vfo(&["VFOB", "1"]), (Some(VFO::VFOB), &["1"][..]));
+        assert_eq!(split_vfo(&["1", "VFOB"]), (None, &["1", "VFOB"][..]));
+    }
+
+    #[test]
+    fn test_parse_frequency() {
+        assert_eq!(parse_frequency("14074000"), Ok(14074000));
+        assert_eq!(parse_frequency("7074000.6"), Ok(7074001));
+        assert_eq!(parse_frequency("-1"), Err(ReturnCode::EINVAL));
+        assert_eq!(arg(&["VFOA"], 1), Err(ReturnCode::EINVAL));
+    }
+
+    #[test]
+    fn test_redact() {
+        assert_eq!(redact("|\\password s3cr3t\n"), "|\\password ***\n");
+        assert_eq!(redact("password: s3cr3t|RPRT 0"), "password: ***|RPRT 0");
+        assert_eq!(redact("get_freq: VFOA|Frequency: 14074000|RPRT 0"), "get_freq: VFOA|Frequency: 14074000|RPRT 0");
+    }
+
+    #[test]
+    fn test_decoder_partial_lines() {
+        let mut decoder = Decoder::new(ProtocolMode::ExtendedPipe);
+        decoder.feed(b"get_freq: VFOA|Frequency: 1407");
+        assert_eq!(decoder.next_response("get_freq VFOA").unwrap(), None);
+
+        decoder.feed(b"4000|RPRT 0\nset_ptt: VFOA 1|RPRT 0\n");
+        assert_eq!(decoder.next_response("get_freq VFOA").unwrap(), Some("get_freq: VFOA|Frequency: 14074000|RPRT 0".to_string()));
+        assert_eq!(decoder.next_response("set_ptt VFOA 1").unwrap(), Some("set_ptt: VFOA 1|RPRT 0".to_string()));
+        assert_eq!(decoder.next_response("get_freq VFOA").unwrap(), None);
+    }
+
+    #[test]
+    fn test_decoder_discards_late_replies() {
+        let mut decoder = Decoder::new(ProtocolMode::ExtendedPipe);
+        decoder.feed(b"set_ptt: VFOA 1|RPRT 0\nget_freq: VFOA|Frequency: 14074000|RPRT 0\n");
+        assert_eq!(decoder.next_response("get_freq VFOA").unwrap(), Some("get_freq: VFOA|Frequency: 14074000|RPRT 0".to_string()));
+
+        let mut decoder = Decoder::new(ProtocolMode::ExtendedNewline);
+        decoder.feed(b"get_mode: VFOA\nMode: USB\nPassband: 2400\nRPRT 0\nget_vfo: currVFO\nVFO: VFOA\nRPRT 0\n");
+        assert_eq!(decoder.next_response("get_vfo").unwrap(), Some("get_vfo: currVFO|VFO: VFOA|RPRT 0".to_string()));
+    }
+
+    #[test]
+    fn test_decoder_sets_events_aside() {
+        let mut decoder = Decoder::new(ProtocolMode::ExtendedNewline);
+        decoder.feed(b"get_freq: VFOA\nEvent: PTT changed to 1 on VFOA\nFrequency: 14074000\nRPRT 0\nEvent: vfo chan");
+
+        let actual = decoder.next_response("get_freq VFOA").unwrap();
+        assert_eq!(actual, Some("get_freq: VFOA|Frequency: 14074000|RPRT 0".to_string()));
+        assert_eq!(decoder.next_event(), Some("Event: PTT changed to 1 on VFOA".to_string()));
+        assert_eq!(decoder.next_event(), None);
+
+        decoder.feed(b"ged to VFOB\nEvent: \xff\n");
+        assert_eq!(decoder.next_event(), Some("Event: vfo changed to VFOB".to_string()));
+        assert_eq!(decoder.next_event(), Some("Event: \u{fffd}".to_string()));
+        assert_eq!(decoder.next_response("get_freq VFOA").unwrap(), None);
+    }
+
+    #[test]
+    fn test_decoder_extended_newline() {
+        let mut decoder = Decoder::new(ProtocolMode::ExtendedNewline);
+        decoder.feed(b"get_mode: VFOA\nMode: USB\n");
+        assert_eq!(decoder.next_response("get_mode VFOA").unwrap(), None);
+
+        decoder.feed(b"Passband: 2400\nRPRT 0\n");
+        let actual = decoder.next_response("get_mode VFOA").unwrap().unwrap();
+        assert_eq!(actual, "get_mode: VFOA|Mode: USB|Passband: 2400|RPRT 0");
+        assert!(Command::GetMode(VFO::VFOA).parse_response(&actual).is_ok());
+    }
+
+    #[test]
+    fn test_decoder_default() {
+        let mut decoder = Decoder::new(ProtocolMode::Default);
+        decoder.feed(b"0\nVFOB\n14074000\nRPRT -11\nRPRT 0\n");
+
+        let actual = decoder.next_response("get_split_vfo 0").unwrap().unwrap();
+        assert_eq!(actual, "get_split_vfo: currVFO|Split: 0|TX VFO: VFOB|RPRT 0");
+        assert!(Command::GetSplitVfo.parse_response(&actual).is_ok());
+
+        let actual = decoder.next_response("get_freq VFOA").unwrap().unwrap();
+        assert_eq!(actual, "get_freq: VFOA|Frequency: 14074000|RPRT 0");
+
+        let actual = decoder.next_response("get_mode VFOA").unwrap().unwrap();
+        assert!(matches!(Command::GetMode(VFO::VFOA).parse_response(&actual), Err(RigCtlError::HamlibError(_))));
+
+        let actual = decoder.next_response("set_ptt VFOA 1").unwrap().unwrap();
+        assert!(Command::SetPtt(VFO::VFOA, PTT::TX).parse_response(&actual).is_ok());
+    }
+
+    #[test]
+    fn test_decoder_framed_raw() {
+        let mut decoder = Decoder::new(ProtocolMode::Default);
+        decoder.feed(b"dump_state:\n1\n2\ndone\nRPRT 0\n14074000\n");
+
+        let actual = decoder.next_framed(ProtocolMode::ExtendedNewline, "dump_state").unwrap();
+        assert_eq!(actual, Some("dump_state:|1|2|done|RPRT 0".to_string()));
+        assert_eq!(decoder.next_response("get_freq VFOA").unwrap(), Some("get_freq: VFOA|Frequency: 14074000|RPRT 0".to_string()));
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let command = Command::GetFreq(VFO::VFOA);
+        let actual = command.parse_response("get_freq: VFOA|Frequency: 14074000|RPRT 0").unwrap();
+        assert_eq!(actual, Response::Freq(get_freq::Response { query_vfo: VFO::VFOA, frequency: 14074000u64 }));
+        assert!(matches!(command.parse_response("get_freq: VFOA|RPRT -11"), Err(RigCtlError::HamlibError(_))));
+    }
+}
@@ -0,0 +1,460 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use crate::cache::{CacheConfig, ResponseCache};
+use crate::commands::raw;
+use crate::error::{ReturnCode, RigCtlError};
+use crate::handle::RigCtlHandle;
+use crate::protocol;
+use crate::protocol::Request;
+use crate::ptt::PTT;
+use crate::server;
+use crate::vfo::VFO;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::time;
+use tokio::time::MissedTickBehavior;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProxyConfig {
+    pub poll_interval: Duration,
+    pub poll_commands: Vec<String>,
+    pub cache: CacheConfig,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_millis(250),
+            poll_commands: ["get_freq", "get_mode", "get_vfo", "get_ptt", "get_split_vfo"].map(String::from).to_vec(),
+            cache: CacheConfig::new(Duration::from_secs(1)),
+        }
+    }
+}
+
+type Fields = Vec<(String, String)>;
+
+struct PttOwner {
+    client: u64,
+    release: String,
+}
+
+struct Core {
+    upstream: RigCtlHandle,
+    // The upstream dump_state, fetched once at bind; clients get the
+    // capabilities of the real rig rather than made up ones
+    state: Result<Vec<String>, ReturnCode>,
+    cache: Mutex<ResponseCache>,
+    // Held while a state-changing command is forwarded, so that the PTT
+    // check and the command itself cannot interleave with other clients
+    arbiter: tokio::sync::Mutex<Option<PttOwner>>,
+}
+
+impl Core {
+    async fn handle(&self, client: u64, request: &Request) -> Result<Fields, ReturnCode> {
+        let line = compose(request);
+
+        if request.command.starts_with("get_") {
+            return self.get(&cache_key(&line)).await;
+        }
+
+        let mut owner = self.arbiter.lock().await;
+        if let Some(current) = owner.as_ref() {
+            if current.client != client {
+                log::debug!("Rejecting {} from client {}: PTT held by client {}", line, client, current.client);
+                return Err(ReturnCode::ERJCTED);
+            }
+        }
+
+        let result = self.forward(&line).await;
+
+        if request.command == "set_ptt" {
+            let (vfo, args) = protocol::split_vfo(&request.args);
+            let keyed = match args.first().and_then(|arg| arg.parse::<PTT>().ok()) {
+                Some(PTT::RX) | None => None,
+                Some(_) => Some(PttOwner {
+                    client,
+                    release: match vfo {
+                        Some(_) => format!("set_ptt {} {}", request.args[0], PTT::RX),
+                        None => format!("set_ptt {}", PTT::RX),
+                    },
+                }),
+            };
+
+            match &result {
+                Ok(_) => *owner = keyed,
+                // Without a reply the rig may have keyed anyway, so the
+                // client keeps the lock until it unkeys or goes away
+                Err(ReturnCode::ETIMEOUT | ReturnCode::EIO) if keyed.is_some() => *owner = keyed,
+                Err(_) => {}
+            }
+        }
+
+        result
+    }
+
+    async fn get(&self, line: &str) -> Result<Fields, ReturnCode> {
+        let cached = self.lock_cache().get(line);
+        let reply = match cached {
+            Some(reply) => reply,
+            None => {
+                let reply = self.upstream.execute_command(line).await.map_err(return_code)?;
+                self.lock_cache().update(line, Some(&reply));
+                reply
+            }
+        };
+
+        fields(&reply)
+    }
+
+    async fn forward(&self, line: &str) -> Result<Fields, ReturnCode> {
+        let result = self.upstream.execute_command(line).await;
+        self.lock_cache().update(line, None);
+        fields(&result.map_err(return_code)?)
+    }
+
+    // Drops the rig out of transmit when the client holding PTT goes away
+    async fn release(&self, client: u64) {
+        let mut owner = self.arbiter.lock().await;
+        if owner.as_ref().is_some_and(|current| current.client == client) {
+            let release = owner.take().map(|current| current.release).unwrap_or_default();
+            log::info!("Client {} disconnected while holding PTT, releasing", client);
+            if let Err(code) = self.forward(&release).await {
+                log::warn!("Unable to release PTT: {}", code);
+            }
+        }
+    }
+
+    async fn poll(&self, commands: &[String]) {
+        for command in commands {
+            let command = cache_key(command);
+            match self.upstream.execute_command(&command).await {
+                Ok(reply) => self.lock_cache().update(&command, Some(&reply)),
+                Err(e) => log::debug!("Polling {} failed: {}", command, e),
+            }
+        }
+    }
+
+    fn lock_cache(&self) -> MutexGuard<'_, ResponseCache> {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// Shares one upstream connection between many rigctl clients: reads are
+// answered from a cache kept fresh by a poller, writes are forwarded one at
+// a time and only the client that keyed the rig may change it or unkey it
+pub struct RigProxy {
+    listener: TcpListener,
+    core: Arc<Core>,
+    config: ProxyConfig,
+}
+
+impl RigProxy {
+    pub async fn bind<A: ToSocketAddrs>(address: A, upstream: RigCtlHandle, config: ProxyConfig) -> io::Result<Self> {
+        let state = match upstream.execute_raw("dump_state", &[]).await {
+            Ok(response) if response.is_ok() => Ok(response.fields.into_iter().map(|field| field.value).collect()),
+            Ok(response) => Err(ReturnCode::from(response.code)),
+            Err(e) => Err(return_code(e)),
+        };
+        if let Err(code) = &state {
+            log::warn!("Unable to fetch dump_state from upstream: {}", code);
+        }
+
+        let core = Core {
+            upstream,
+            state,
+            cache: Mutex::new(ResponseCache::new(config.cache.clone())),
+            arbiter: tokio::sync::Mutex::new(None),
+        };
+
+        Ok(Self {
+            listener: TcpListener::bind(address).await?,
+            core: Arc::new(core),
+            config,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub async fn run(self) -> io::Result<()> {
+        let poller = match self.config.poll_interval.is_zero() || self.config.poll_commands.is_empty() {
+            true => None,
+            false => Some(tokio::spawn(poll(self.core.clone(), self.config.clone()))),
+        };
+
+        let result = self.accept().await;
+
+        if let Some(poller) = poller {
+            poller.abort();
+        }
+
+        result
+    }
+
+    async fn accept(&self) -> io::Result<()> {
+        let clients = AtomicU64::new(0);
+
+        loop {
+            let (stream, peer) = self.listener.accept().await?;
+            stream.set_nodelay(true)?;
+
+            let client = clients.fetch_add(1, Ordering::Relaxed);
+            log::debug!("Client {} connected from {}", client, peer);
+
+            let core = self.core.clone();
+            tokio::spawn(async move {
+                if let Err(e) = serve(stream, &core, client).await {
+                    log::debug!("Client {} failed: {}", client, e);
+                }
+                core.release(client).await;
+                log::debug!("Client {} disconnected", client);
+            });
+        }
+    }
+}
+
+async fn poll(core: Arc<Core>, config: ProxyConfig) {
+    let mut interval = time::interval(config.poll_interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+        core.poll(&config.poll_commands).await;
+    }
+}
+
+async fn serve(stream: TcpStream, core: &Core, client: u64) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let reply = match protocol::decode_request(&line) {
+            None => format!("RPRT {}\n", ReturnCode::EINVAL.code()),
+            Some(request) => match request.command.as_str() {
+                "quit" => return Ok(()),
+                "dump_state" => match &core.state {
+                    Ok(lines) => server::encode_lines(&request, lines),
+                    Err(code) => protocol::encode_reply::<&str>(&request, &Err(*code)),
+                },
+                _ => protocol::encode_reply(&request, &core.handle(client, &request).await),
+            },
+        };
+
+        writer.write_all(reply.as_bytes()).await?;
+    }
+
+    Ok(())
+}
+
+fn compose(request: &Request) -> String {
+    let mut line = request.command.clone();
+    for arg in &request.args {
+        line.push(' ');
+        line.push_str(arg);
+    }
+    line
+}
+
+// Reads of the current VFO are the same whether or not the client names it,
+// so they share one cache entry and one upstream request
+fn cache_key(line: &str) -> String {
+    let mut tokens = line.split_whitespace();
+    let mut key = tokens.next().unwrap_or_default().to_string();
+    let args = tokens.collect::<Vec<&str>>();
+
+    let (vfo, args) = protocol::split_vfo(&args);
+    let vfo = vfo.filter(|vfo| *vfo != VFO::CurrVfo).map(|vfo| vfo.to_string());
+    for arg in vfo.iter().map(String::as_str).chain(args.iter().copied()) {
+        key.push(' ');
+        key.push_str(arg);
+    }
+    key
+}
+
+fn fields(reply: &str) -> Result<Fields, ReturnCode> {
+    let response = raw::parse(reply).map_err(|_| ReturnCode::EPROTO)?;
+    Ok(response.fields.into_iter().map(|field| (field.name, field.value)).collect())
+}
+
+fn return_code(error: RigCtlError) -> ReturnCode {
+    match error {
+        RigCtlError::HamlibError(code) => code,
+        RigCtlError::CommunicationTimeout => ReturnCode::ETIMEOUT,
+        RigCtlError::ResponseParsing(_) | RigCtlError::RawDataError(_) => ReturnCode::EPROTO,
+        _ => ReturnCode::EIO,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_ptt_arbitration() {
+        use crate::mock::MockServer;
+        use crate::RigCtlClient;
+
+        let server = MockServer::start().await.unwrap();
+        let upstream = RigCtlHandle::spawn(server.client());
+        upstream.connect().await.unwrap();
+
+        let proxy = RigProxy::bind("127.0.0.1:0", upstream, ProxyConfig::default()).await.unwrap();
+        let port = proxy.local_addr().unwrap().port();
+        tokio::spawn(proxy.run());
+
+        let mut first = RigCtlClient::new("127.0.0.1", port, None);
+        let mut second = RigCtlClient::new("127.0.0.1", port, None);
+        first.connect().await.unwrap();
+        second.connect().await.unwrap();
+
+        assert_eq!(second.get_freq(VFO::VFOA).await.unwrap().frequency, 14074000);
+        first.set_ptt(VFO::VFOA, PTT::TX).await.unwrap();
+        assert!(matches!(second.set_ptt(VFO::VFOA, PTT::RX).await, Err(RigCtlError::HamlibError(ReturnCode::ERJCTED))));
+        assert_eq!(second.execute_raw("set_freq", &["VFOA", "7074000"]).await.unwrap().code, ReturnCode::ERJCTED.code());
+        assert_eq!(server.rig().ptt, PTT::TX);
+
+        first.disconnect();
+        for _ in 0..50 {
+            if server.rig().ptt == PTT::RX {
+                break;
+            }
+            time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(server.rig().ptt, PTT::RX);
+        second.set_ptt(VFO::VFOA, PTT::TX).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ptt_kept_after_timeout() {
+        use crate::connection::ReconnectPolicy;
+        use crate::mock::MockServer;
+        use crate::RigCtlClient;
+
+        let server = MockServer::start().await.unwrap();
+        let client = RigCtlClient::builder("127.0.0.1")
+            .port(server.port())
+            .command_timeout(Duration::from_millis(100))
+            .reconnect_policy(ReconnectPolicy { initial_backoff: Duration::from_millis(10), ..ReconnectPolicy::default() })
+            .build();
+        let upstream = RigCtlHandle::spawn(client);
+        upstream.connect().await.unwrap();
+
+        let config = ProxyConfig { poll_interval: Duration::ZERO, ..ProxyConfig::default() };
+        let proxy = RigProxy::bind("127.0.0.1:0", upstream, config).await.unwrap();
+        let port = proxy.local_addr().unwrap().port();
+        tokio::spawn(proxy.run());
+
+        let mut first = RigCtlClient::new("127.0.0.1", port, None);
+        let mut second = RigCtlClient::new("127.0.0.1", port, None);
+        first.connect().await.unwrap();
+        second.connect().await.unwrap();
+
+        server.set_delay(Some(Duration::from_millis(300)));
+        assert!(matches!(first.set_ptt(VFO::VFOA, PTT::TX).await, Err(RigCtlError::HamlibError(ReturnCode::ETIMEOUT))));
+        server.set_delay(None);
+        assert_eq!(server.rig().ptt, PTT::TX);
+        assert_eq!(second.execute_raw("set_ptt", &["VFOA", "0"]).await.unwrap().code, ReturnCode::ERJCTED.code());
+
+        first.disconnect();
+        for _ in 0..50 {
+            if server.rig().ptt == PTT::RX {
+                break;
+            }
+            time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(server.rig().ptt, PTT::RX);
+    }
+
+    #[tokio::test]
+    async fn test_dump_state_from_upstream() {
+        use crate::mode::Mode;
+        use crate::server::{BackendFuture, Capabilities, RigBackend, RigServer};
+        use crate::RigCtlClient;
+        use tokio::io::AsyncReadExt;
+
+        struct Backend;
+
+        impl RigBackend for Backend {
+            fn capabilities(&self) -> Capabilities {
+                Capabilities { frequency_ranges: vec![1800000..=29700000], ..Capabilities::default() }
+            }
+
+            fn get_freq(&self, _vfo: VFO) -> BackendFuture<'_, u64> {
+                Box::pin(async { Ok(14074000) })
+            }
+
+            fn set_freq(&self, _vfo: VFO, _frequency: u64) -> BackendFuture<'_, ()> {
+                Box::pin(async { Ok(()) })
+            }
+
+            fn get_mode(&self, _vfo: VFO) -> BackendFuture<'_, (Mode, u64)> {
+                Box::pin(async { Ok((Mode::USB, 2400)) })
+            }
+
+            fn set_mode(&self, _vfo: VFO, _mode: Mode, _passband: i64) -> BackendFuture<'_, ()> {
+                Box::pin(async { Ok(()) })
+            }
+        }
+
+        let server = RigServer::bind("127.0.0.1:0", Arc::new(Backend)).await.unwrap();
+        let upstream_port = server.local_addr().unwrap().port();
+        tokio::spawn(server.run());
+
+        let upstream = RigCtlHandle::spawn(RigCtlClient::new("127.0.0.1", upstream_port, None));
+        upstream.connect().await.unwrap();
+        let proxy = RigProxy::bind("127.0.0.1:0", upstream, ProxyConfig::default()).await.unwrap();
+        let port = proxy.local_addr().unwrap().port();
+        tokio::spawn(proxy.run());
+
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        stream.write_all(b"\\dump_state\nq\n").await.unwrap();
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).await.unwrap();
+
+        let lines = reply.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[0], "1");
+        assert!(lines[3].starts_with("1800000.000000 29700000.000000 0x"));
+        assert_eq!(lines.last(), Some(&"done"));
+    }
+
+    #[test]
+    fn test_cache_key() {
+        assert_eq!(cache_key("get_freq"), "get_freq");
+        assert_eq!(cache_key("get_freq currVFO"), "get_freq");
+        assert_eq!(cache_key("get_freq VFOA"), "get_freq VFOA");
+        assert_eq!(cache_key("get_level  currVFO STRENGTH"), "get_level STRENGTH");
+        assert_eq!(cache_key("get_split_vfo 0"), "get_split_vfo 0");
+    }
+
+    #[test]
+    fn test_return_code() {
+        assert_eq!(return_code(RigCtlError::HamlibError(ReturnCode::ENAVAIL)), ReturnCode::ENAVAIL);
+        assert_eq!(return_code(RigCtlError::CommunicationTimeout), ReturnCode::ETIMEOUT);
+        assert_eq!(return_code(RigCtlError::NotConnected), ReturnCode::EIO);
+    }
+}
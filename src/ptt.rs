@@ -0,0 +1,53 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::error::RigCtlError;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PTT {
+    RX,
+    TX,
+    TXMic,
+    TXData,
+}
+
+impl Display for PTT {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            PTT::RX => write!(f, "0"),
+            PTT::TX => write!(f, "1"),
+            PTT::TXMic => write!(f, "2"),
+            PTT::TXData => write!(f, "3"),
+        }
+    }
+}
+
+impl FromStr for PTT {
+    type Err = RigCtlError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(PTT::RX),
+            "1" => Ok(PTT::TX),
+            "2" => Ok(PTT::TXMic),
+            "3" => Ok(PTT::TXData),
+            _ => Err(RigCtlError::RawDataError(format!("Unable to parse PTT with string \"{}\"", &s))),
+        }
+    }
+}
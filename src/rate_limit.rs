@@ -0,0 +1,156 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimit {
+    pub min_interval: Duration,
+    pub burst: u32,
+    pub refill_interval: Duration,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            min_interval: Duration::from_millis(20),
+            burst: 5,
+            refill_interval: Duration::from_millis(100),
+        }
+    }
+}
+
+impl RateLimit {
+    pub fn new(min_interval: Duration) -> Self {
+        Self {
+            min_interval,
+            burst: 1,
+            refill_interval: min_interval,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    config: RateLimit,
+    tokens: f64,
+    last_refill: Instant,
+    last_command: Option<Instant>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(config: RateLimit) -> Self {
+        Self {
+            tokens: config.burst.max(1) as f64,
+            config,
+            last_refill: Instant::now(),
+            last_command: None,
+        }
+    }
+
+    // Books a slot for the next command and returns how long the caller has
+    // to wait before sending it; PTT commands are never held back, but still
+    // count against the budget of the ones that follow
+    pub(crate) fn reserve(&mut self, command: &str, now: Instant) -> Duration {
+        if is_priority(command) {
+            self.refill(now);
+            self.tokens = (self.tokens - 1.0).max(0.0);
+            self.last_command = Some(self.last_command.map_or(now, |last| last.max(now)));
+            return Duration::ZERO;
+        }
+
+        let mut at = match self.last_command {
+            Some(last) => now.max(last + self.config.min_interval),
+            None => now,
+        };
+
+        self.refill(at);
+        if self.tokens < 1.0 {
+            at += self.config.refill_interval.mul_f64(1.0 - self.tokens);
+            self.refill(at);
+        }
+
+        self.tokens = (self.tokens - 1.0).max(0.0);
+        self.last_command = Some(at);
+
+        at.saturating_duration_since(now)
+    }
+
+    fn refill(&mut self, at: Instant) {
+        if at <= self.last_refill {
+            return;
+        }
+
+        let elapsed = at.duration_since(self.last_refill).as_secs_f64();
+        let interval = self.config.refill_interval.as_secs_f64();
+        let refilled = match interval > 0.0 {
+            true => elapsed / interval,
+            false => f64::INFINITY,
+        };
+
+        self.tokens = (self.tokens + refilled).min(self.config.burst.max(1) as f64);
+        self.last_refill = at;
+    }
+}
+
+fn is_priority(command: &str) -> bool {
+    command.starts_with("set_ptt")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_min_interval() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(RateLimit::new(Duration::from_millis(100)));
+        limiter.last_refill = now;
+
+        assert_eq!(limiter.reserve("get_freq VFOA", now), Duration::ZERO);
+        assert_eq!(limiter.reserve("get_mode VFOA", now), Duration::from_millis(100));
+        assert_eq!(limiter.reserve("get_vfo", now + Duration::from_millis(500)), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_burst() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(RateLimit {
+            min_interval: Duration::ZERO,
+            burst: 3,
+            refill_interval: Duration::from_millis(100),
+        });
+        limiter.last_refill = now;
+
+        for _ in 0..3 {
+            assert_eq!(limiter.reserve("get_freq VFOA", now), Duration::ZERO);
+        }
+        let delay = limiter.reserve("get_freq VFOA", now);
+        assert!(delay >= Duration::from_millis(99) && delay <= Duration::from_millis(101));
+    }
+
+    #[test]
+    fn test_ptt_bypass() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(RateLimit::new(Duration::from_millis(100)));
+        limiter.last_refill = now;
+
+        assert_eq!(limiter.reserve("get_freq VFOA", now), Duration::ZERO);
+        assert_eq!(limiter.reserve("set_ptt VFOA 0", now), Duration::ZERO);
+        assert!(limiter.reserve("get_freq VFOA", now) > Duration::ZERO);
+    }
+}
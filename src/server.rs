@@ -0,0 +1,435 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use crate::error::ReturnCode;
+use crate::mode::Mode;
+use crate::protocol;
+use crate::protocol::{arg, parse_arg, parse_frequency, ProtocolMode, Request, Values};
+use crate::ptt::PTT;
+use crate::vfo::VFO;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::ops::RangeInclusive;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, ToSocketAddrs};
+
+pub type BackendFuture<'a, T> = Pin<Box<dyn Future<Output=Result<T, ReturnCode>> + Send + 'a>>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capabilities {
+    pub frequency_ranges: Vec<RangeInclusive<u64>>,
+    pub modes: Vec<Mode>,
+    pub filters: Vec<(Mode, u64)>,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            frequency_ranges: vec![150000..=1500000000],
+            modes: vec![Mode::AM, Mode::CW, Mode::USB, Mode::LSB, Mode::RTTY, Mode::FM, Mode::PKTUSB, Mode::PKTLSB],
+            filters: vec![
+                (Mode::USB, 2400),
+                (Mode::LSB, 2400),
+                (Mode::PKTUSB, 2400),
+                (Mode::PKTLSB, 2400),
+                (Mode::CW, 500),
+                (Mode::RTTY, 500),
+                (Mode::AM, 6000),
+                (Mode::FM, 15000),
+            ],
+        }
+    }
+}
+
+// The rig behind a server; only frequency and mode are mandatory, anything
+// else answers ENIMPL unless overridden. VFO::CurrVfo is passed when the
+// client did not target a specific VFO
+pub trait RigBackend: Send + Sync {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn get_info(&self) -> BackendFuture<'_, String> {
+        not_implemented()
+    }
+
+    fn get_freq(&self, vfo: VFO) -> BackendFuture<'_, u64>;
+
+    fn set_freq(&self, vfo: VFO, frequency: u64) -> BackendFuture<'_, ()>;
+
+    fn get_mode(&self, vfo: VFO) -> BackendFuture<'_, (Mode, u64)>;
+
+    // A passband of 0 selects the default and a negative one leaves it
+    // unchanged, as in Hamlib
+    fn set_mode(&self, vfo: VFO, mode: Mode, passband: i64) -> BackendFuture<'_, ()>;
+
+    fn get_vfo(&self) -> BackendFuture<'_, VFO> {
+        Box::pin(async { Ok(VFO::VFOA) })
+    }
+
+    fn set_vfo(&self, _vfo: VFO) -> BackendFuture<'_, ()> {
+        not_implemented()
+    }
+
+    fn get_split_vfo(&self, _vfo: VFO) -> BackendFuture<'_, (bool, VFO)> {
+        not_implemented()
+    }
+
+    fn set_split_vfo(&self, _vfo: VFO, _split: bool, _tx_vfo: VFO) -> BackendFuture<'_, ()> {
+        not_implemented()
+    }
+
+    fn get_split_freq(&self, _vfo: VFO) -> BackendFuture<'_, u64> {
+        not_implemented()
+    }
+
+    fn set_split_freq(&self, _vfo: VFO, _frequency: u64) -> BackendFuture<'_, ()> {
+        not_implemented()
+    }
+
+    fn get_split_mode(&self, _vfo: VFO) -> BackendFuture<'_, (Mode, u64)> {
+        not_implemented()
+    }
+
+    fn set_split_mode(&self, _vfo: VFO, _mode: Mode, _passband: i64) -> BackendFuture<'_, ()> {
+        not_implemented()
+    }
+
+    fn get_ptt(&self, _vfo: VFO) -> BackendFuture<'_, PTT> {
+        not_implemented()
+    }
+
+    fn set_ptt(&self, _vfo: VFO, _ptt: PTT) -> BackendFuture<'_, ()> {
+        not_implemented()
+    }
+
+    fn get_level<'a>(&'a self, _vfo: VFO, _level: &'a str) -> BackendFuture<'a, f64> {
+        not_implemented()
+    }
+
+    fn set_level<'a>(&'a self, _vfo: VFO, _level: &'a str, _value: f64) -> BackendFuture<'a, ()> {
+        not_implemented()
+    }
+}
+
+fn not_implemented<'a, T: Send + 'a>() -> BackendFuture<'a, T> {
+    Box::pin(async { Err(ReturnCode::ENIMPL) })
+}
+
+pub struct RigServer {
+    listener: TcpListener,
+    backend: Arc<dyn RigBackend>,
+}
+
+impl RigServer {
+    pub async fn bind<A: ToSocketAddrs>(address: A, backend: Arc<dyn RigBackend>) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(address).await?,
+            backend,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub async fn run(self) -> io::Result<()> {
+        loop {
+            let (stream, peer) = self.listener.accept().await?;
+            stream.set_nodelay(true)?;
+            log::debug!("Accepted rigctl connection from {}", peer);
+
+            let backend = self.backend.clone();
+            tokio::spawn(async move {
+                if let Err(e) = serve(stream, backend).await {
+                    log::debug!("Connection from {} closed: {}", peer, e);
+                }
+            });
+        }
+    }
+}
+
+// Serves one client until it disconnects or sends `quit`
+pub async fn serve<S: AsyncRead + AsyncWrite + Send>(stream: S, backend: Arc<dyn RigBackend>) -> io::Result<()> {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let request = match protocol::decode_request(&line) {
+            Some(request) => request,
+            None => {
+                writer.write_all(format!("RPRT {}\n", ReturnCode::EINVAL.code()).as_bytes()).await?;
+                continue;
+            }
+        };
+
+        log::trace!(" <<< [{}]", protocol::redact(line.trim()));
+
+        let reply = match request.command.as_str() {
+            "quit" => return Ok(()),
+            "dump_state" => encode_lines(&request, &dump_state(&backend.capabilities())),
+            _ => protocol::encode_reply(&request, &dispatch(backend.as_ref(), &request).await),
+        };
+
+        log::trace!(" >>> [{}]", reply.trim_end());
+        writer.write_all(reply.as_bytes()).await?;
+        writer.flush().await?;
+    }
+
+    Ok(())
+}
+
+pub async fn dispatch(backend: &dyn RigBackend, request: &Request) -> Result<Values, ReturnCode> {
+    let (vfo, args) = protocol::split_vfo(&request.args);
+    let target = vfo.unwrap_or(VFO::CurrVfo);
+
+    match request.command.as_str() {
+        "get_info" => Ok(vec![("Info", backend.get_info().await?)]),
+        "get_freq" => Ok(vec![("Frequency", backend.get_freq(target).await?.to_string())]),
+        "set_freq" => {
+            backend.set_freq(target, parse_frequency(arg(args, 0)?)?).await?;
+            Ok(vec![])
+        }
+        "get_mode" => {
+            let (mode, passband) = backend.get_mode(target).await?;
+            Ok(vec![("Mode", mode.to_string()), ("Passband", passband.to_string())])
+        }
+        "set_mode" => {
+            backend.set_mode(target, parse_arg(arg(args, 0)?)?, parse_passband(args)?).await?;
+            Ok(vec![])
+        }
+        "get_vfo" => Ok(vec![("VFO", backend.get_vfo().await?.to_string())]),
+        "set_vfo" => {
+            backend.set_vfo(vfo.ok_or(ReturnCode::EINVAL)?).await?;
+            Ok(vec![])
+        }
+        "get_split_vfo" => {
+            let (split, tx_vfo) = backend.get_split_vfo(target).await?;
+            Ok(vec![("Split", (split as u8).to_string()), ("TX VFO", tx_vfo.to_string())])
+        }
+        "set_split_vfo" => {
+            let split = parse_arg::<u8>(arg(args, 0)?)? != 0;
+            backend.set_split_vfo(target, split, parse_arg(arg(args, 1)?)?).await?;
+            Ok(vec![])
+        }
+        "get_split_freq" => Ok(vec![("TX Frequency", backend.get_split_freq(target).await?.to_string())]),
+        "set_split_freq" => {
+            backend.set_split_freq(target, parse_frequency(arg(args, 0)?)?).await?;
+            Ok(vec![])
+        }
+        "get_split_mode" => {
+            let (mode, passband) = backend.get_split_mode(target).await?;
+            Ok(vec![("TX Mode", mode.to_string()), ("TX Passband", passband.to_string())])
+        }
+        "set_split_mode" => {
+            backend.set_split_mode(target, parse_arg(arg(args, 0)?)?, parse_passband(args)?).await?;
+            Ok(vec![])
+        }
+        "get_ptt" => Ok(vec![("PTT", backend.get_ptt(target).await?.to_string())]),
+        "set_ptt" => {
+            backend.set_ptt(target, parse_arg(arg(args, 0)?)?).await?;
+            Ok(vec![])
+        }
+        "get_level" => Ok(vec![("Level Value", backend.get_level(target, arg(args, 0)?).await?.to_string())]),
+        "set_level" => {
+            backend.set_level(target, arg(args, 0)?, parse_arg(arg(args, 1)?)?).await?;
+            Ok(vec![])
+        }
+        "chk_vfo" => Ok(vec![("ChkVFO", "0".to_string())]),
+        "get_powerstat" => Ok(vec![("Power Status", "1".to_string())]),
+        _ => Err(ReturnCode::ENIMPL),
+    }
+}
+
+// Hamlib's RIG_MODE_* bits
+pub fn mode_bit(mode: Mode) -> u64 {
+    let bit = match mode {
+        Mode::AM => 0,
+        Mode::CW => 1,
+        Mode::USB => 2,
+        Mode::LSB => 3,
+        Mode::RTTY => 4,
+        Mode::FM => 5,
+        Mode::WFM => 6,
+        Mode::CWR => 7,
+        Mode::RTTYR => 8,
+        Mode::AMS => 9,
+        Mode::PKTLSB => 10,
+        Mode::PKTUSB => 11,
+        Mode::PKTFM => 12,
+        Mode::ECSSUSB => 13,
+        Mode::ECSSLSB => 14,
+        Mode::FAX => 15,
+        Mode::SAM => 16,
+        Mode::SAL => 17,
+        Mode::SAH => 18,
+        Mode::DSB => 19,
+    };
+    1 << bit
+}
+
+// The capability dump Hamlib's NET rigctl backend reads when opening the
+// connection, in protocol version 1 with the key=value trailer
+pub(crate) fn dump_state(capabilities: &Capabilities) -> Vec<String> {
+    let modes = capabilities.modes.iter().fold(0, |mask, mode| mask | mode_bit(*mode));
+    let mut lines = vec!["1".to_string(), "2".to_string(), "2".to_string()];
+
+    for power in ["-1 -1", "1 100000"] {
+        for range in &capabilities.frequency_ranges {
+            lines.push(format!("{}.000000 {}.000000 0x{:x} {} 0x3 0x0", range.start(), range.end(), modes, power));
+        }
+        lines.push("0 0 0 0 0 0 0".to_string());
+    }
+
+    lines.push(format!("0x{:x} 1", modes));
+    lines.push("0 0".to_string());
+
+    for (mode, width) in &capabilities.filters {
+        lines.push(format!("0x{:x} {}", mode_bit(*mode), width));
+    }
+    lines.push("0 0".to_string());
+
+    // max_rit, max_xit, max_ifshift, announces, preamps, attenuators and the
+    // func, level and parm masks
+    lines.extend(["0", "0", "0", "0", "0", "0"].map(String::from));
+    lines.extend(["0x0", "0x0", "0x0", "0x0", "0x0", "0x0"].map(String::from));
+
+    lines.extend(["vfo_ops=0x0", "ptt_type=0x1", "targetable_vfo=0x3", "has_set_vfo=1", "has_get_vfo=1", "has_set_freq=1", "has_get_freq=1", "done"].map(String::from));
+
+    lines
+}
+
+pub(crate) fn encode_lines(request: &Request, lines: &[String]) -> String {
+    match request.mode {
+        ProtocolMode::Default => format!("{}\n", lines.join("\n")),
+        ProtocolMode::ExtendedNewline => format!("{}:\n{}\nRPRT 0\n", request.command, lines.join("\n")),
+        ProtocolMode::ExtendedPipe => format!("{}:|{}|RPRT 0\n", request.command, lines.join("|")),
+    }
+}
+
+fn parse_passband(args: &[String]) -> Result<i64, ReturnCode> {
+    args.get(1).map(|value| parse_arg::<i64>(value)).transpose().map(|passband| passband.unwrap_or(-1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RigCtlClient;
+    use std::sync::Mutex;
+    use tokio::io::AsyncReadExt;
+
+    struct TestBackend {
+        state: Mutex<(u64, Mode, u64, PTT)>,
+    }
+
+    impl RigBackend for TestBackend {
+        fn get_freq(&self, _vfo: VFO) -> BackendFuture<'_, u64> {
+            let frequency = self.state.lock().unwrap().0;
+            Box::pin(async move { Ok(frequency) })
+        }
+
+        fn set_freq(&self, _vfo: VFO, frequency: u64) -> BackendFuture<'_, ()> {
+            self.state.lock().unwrap().0 = frequency;
+            Box::pin(async { Ok(()) })
+        }
+
+        fn get_mode(&self, _vfo: VFO) -> BackendFuture<'_, (Mode, u64)> {
+            let (_, mode, passband, _) = *self.state.lock().unwrap();
+            Box::pin(async move { Ok((mode, passband)) })
+        }
+
+        fn set_mode(&self, _vfo: VFO, mode: Mode, passband: i64) -> BackendFuture<'_, ()> {
+            let mut state = self.state.lock().unwrap();
+            state.1 = mode;
+            if passband > 0 {
+                state.2 = passband as u64;
+            }
+            Box::pin(async { Ok(()) })
+        }
+
+        fn set_ptt(&self, _vfo: VFO, ptt: PTT) -> BackendFuture<'_, ()> {
+            self.state.lock().unwrap().3 = ptt;
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    fn backend() -> Arc<TestBackend> {
+        Arc::new(TestBackend {
+            state: Mutex::new((14074000, Mode::USB, 2400, PTT::RX)),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_client_against_server() {
+        let backend = backend();
+        let (client_side, server_side) = tokio::io::duplex(4096);
+        tokio::spawn(serve(server_side, backend.clone()));
+
+        let mut client = RigCtlClient::from_stream(client_side, Some(500));
+        assert_eq!(client.get_freq(VFO::VFOA).await.unwrap().frequency, 14074000);
+        client.set_ptt(VFO::VFOA, PTT::TX).await.unwrap();
+        assert_eq!(backend.state.lock().unwrap().3, PTT::TX);
+        assert_eq!(client.execute_raw("set_freq", &["VFOA", "7074000"]).await.unwrap().code, 0);
+        assert!(client.get_info().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_default_mode_short_commands() {
+        let (mut client_side, server_side) = tokio::io::duplex(4096);
+        tokio::spawn(serve(server_side, backend()));
+
+        client_side.write_all(b"M CW 500\nm\nf\nq\n").await.unwrap();
+        let mut reply = String::new();
+        client_side.read_to_string(&mut reply).await.unwrap();
+        assert_eq!(reply, "RPRT 0\nCW\n500\n14074000\n");
+    }
+
+    #[tokio::test]
+    async fn test_dump_state() {
+        let (mut client_side, server_side) = tokio::io::duplex(4096);
+        tokio::spawn(serve(server_side, backend()));
+
+        client_side.write_all(b"\\dump_state\nq\n").await.unwrap();
+        let mut reply = String::new();
+        client_side.read_to_string(&mut reply).await.unwrap();
+
+        let lines = reply.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[0], "1");
+        assert!(lines[3].starts_with("150000.000000 1500000000.000000 0x"));
+        assert_eq!(lines.last(), Some(&"done"));
+    }
+
+    #[tokio::test]
+    async fn test_raw_dump_state_in_default_mode() {
+        let (client_side, server_side) = tokio::io::duplex(4096);
+        tokio::spawn(serve(server_side, backend()));
+
+        let mut client = RigCtlClient::from_stream(client_side, Some(500));
+        client.set_protocol_mode(ProtocolMode::Default);
+        let response = client.execute_raw("dump_state", &[]).await.unwrap();
+        assert_eq!(response.fields.first().map(|field| field.value.as_str()), Some("1"));
+        assert_eq!(response.fields.last().map(|field| field.value.as_str()), Some("done"));
+        assert_eq!(client.get_freq(VFO::VFOA).await.unwrap().frequency, 14074000);
+    }
+}
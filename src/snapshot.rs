@@ -0,0 +1,172 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use crate::error::RigCtlError;
+use crate::mode::Mode;
+use crate::protocol::Response;
+use crate::ptt::PTT;
+use crate::vfo::VFO;
+use crate::RigCtlClient;
+use time::OffsetDateTime;
+
+// Everything is optional: a field is left empty when the rig or rigctld
+// does not support the command behind it
+#[derive(Debug, Clone, PartialEq)]
+pub struct RigStateSnapshot {
+    pub timestamp: OffsetDateTime,
+    pub vfo: Option<VFO>,
+    pub frequency: Option<u64>,
+    pub mode: Option<Mode>,
+    pub passband: Option<u64>,
+    pub split: Option<bool>,
+    // TX side, only filled while split is on
+    pub tx_vfo: Option<VFO>,
+    pub tx_frequency: Option<u64>,
+    pub tx_mode: Option<Mode>,
+    pub tx_passband: Option<u64>,
+    pub ptt: Option<PTT>,
+    // S-meter in dB relative to S9
+    pub strength: Option<f64>,
+    pub rit: Option<i64>,
+    pub xit: Option<i64>,
+}
+
+impl RigStateSnapshot {
+    pub(crate) fn empty(timestamp: OffsetDateTime) -> Self {
+        Self {
+            timestamp,
+            vfo: None,
+            frequency: None,
+            mode: None,
+            passband: None,
+            split: None,
+            tx_vfo: None,
+            tx_frequency: None,
+            tx_mode: None,
+            tx_passband: None,
+            ptt: None,
+            strength: None,
+            rit: None,
+            xit: None,
+        }
+    }
+}
+
+// All the queries go out in a single batch, so the whole snapshot costs one
+// round trip; per-command failures only leave the matching fields empty
+pub(crate) async fn take(client: &mut RigCtlClient) -> Result<RigStateSnapshot, RigCtlError> {
+    let vfo = VFO::CurrVfo;
+    let results = client.batch()
+        .get_vfo()
+        .get_freq(vfo)
+        .get_mode(vfo)
+        .get_split_vfo()
+        .get_split_freq(vfo)
+        .get_split_mode(vfo)
+        .get_ptt(vfo)
+        .get_level(vfo, "STRENGTH")
+        .get_rit(vfo)
+        .get_xit(vfo)
+        .execute()
+        .await?;
+
+    let mut snapshot = RigStateSnapshot::empty(OffsetDateTime::now_utc());
+    for result in results {
+        let response = match result {
+            Ok(response) => response,
+            Err(e) => {
+                log::debug!("Snapshot field unavailable: {}", e);
+                continue;
+            }
+        };
+
+        match response {
+            Response::Vfo(response) => snapshot.vfo = Some(response.vfo),
+            Response::Freq(response) => snapshot.frequency = Some(response.frequency),
+            Response::Mode(response) => {
+                snapshot.mode = Some(response.mode);
+                snapshot.passband = Some(response.passband);
+            }
+            Response::SplitVfo(response) => {
+                snapshot.split = Some(response.split);
+                snapshot.tx_vfo = Some(response.tx_vfo);
+            }
+            Response::SplitFreq(response) => snapshot.tx_frequency = Some(response.frequency),
+            Response::SplitMode(response) => {
+                snapshot.tx_mode = Some(response.mode);
+                snapshot.tx_passband = Some(response.passband);
+            }
+            Response::PttState(response) => snapshot.ptt = Some(response.ptt),
+            Response::Level(response) => snapshot.strength = Some(response.value),
+            Response::Rit(response) => snapshot.rit = Some(response.offset),
+            Response::Xit(response) => snapshot.xit = Some(response.offset),
+            _ => {}
+        }
+    }
+
+    if snapshot.split != Some(true) {
+        snapshot.tx_vfo = None;
+        snapshot.tx_frequency = None;
+        snapshot.tx_mode = None;
+        snapshot.tx_passband = None;
+    }
+
+    Ok(snapshot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockServer;
+
+    #[tokio::test]
+    async fn test_snapshot_skips_unsupported_fields() {
+        let server = MockServer::start().await.unwrap();
+        server.update_rig(|rig| rig.split = true);
+        let mut client = server.client();
+        client.connect().await.unwrap();
+
+        let snapshot = client.snapshot().await.unwrap();
+
+        assert_eq!(snapshot.vfo, Some(VFO::VFOA));
+        assert_eq!(snapshot.frequency, Some(14074000));
+        assert_eq!(snapshot.mode, Some(Mode::USB));
+        assert_eq!(snapshot.passband, Some(3000));
+        assert_eq!(snapshot.split, Some(true));
+        assert_eq!(snapshot.tx_vfo, Some(VFO::VFOB));
+        assert_eq!(snapshot.tx_frequency, Some(7074000));
+        assert_eq!(snapshot.tx_mode, Some(Mode::LSB));
+        assert_eq!(snapshot.ptt, Some(PTT::RX));
+        assert_eq!(snapshot.strength, Some(-54f64));
+        assert_eq!(snapshot.rit, None);
+        assert_eq!(snapshot.xit, None);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_without_split() {
+        let server = MockServer::start().await.unwrap();
+        let mut client = server.client();
+        client.connect().await.unwrap();
+
+        let snapshot = client.snapshot().await.unwrap();
+
+        assert_eq!(snapshot.split, Some(false));
+        assert_eq!(snapshot.tx_vfo, None);
+        assert_eq!(snapshot.tx_frequency, None);
+        assert_eq!(snapshot.tx_mode, None);
+    }
+}
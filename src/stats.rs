@@ -0,0 +1,230 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use crate::error::RigCtlError;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+const BUCKET_BOUNDS_MS: [u64; 12] = [1, 2, 5, 10, 20, 50, 100, 200, 500, 1000, 2000, 5000];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    bounds: Vec<Duration>,
+    counts: Vec<u64>,
+    count: u64,
+    sum: Duration,
+    min: Option<Duration>,
+    max: Option<Duration>,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        let bounds = BUCKET_BOUNDS_MS.iter()
+            .map(|ms| Duration::from_millis(*ms))
+            .collect::<Vec<Duration>>();
+
+        Self {
+            counts: vec![0; bounds.len() + 1],
+            bounds,
+            count: 0,
+            sum: Duration::ZERO,
+            min: None,
+            max: None,
+        }
+    }
+}
+
+impl Histogram {
+    pub fn record(&mut self, value: Duration) {
+        let index = self.bounds.iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.bounds.len());
+
+        self.counts[index] += 1;
+        self.count += 1;
+        self.sum += value;
+        self.min = Some(self.min.map_or(value, |min| min.min(value)));
+        self.max = Some(self.max.map_or(value, |max| max.max(value)));
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn min(&self) -> Option<Duration> {
+        self.min
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        self.max
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        match self.count {
+            0 => None,
+            count => Some(Duration::from_nanos((self.sum.as_nanos() / count as u128) as u64)),
+        }
+    }
+
+    // Upper bound of the bucket holding the given quantile, None when the
+    // quantile falls in the overflow bucket
+    pub fn quantile(&self, quantile: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let target = ((self.count as f64) * quantile.clamp(0.0, 1.0)).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return self.bounds.get(index).copied();
+            }
+        }
+
+        None
+    }
+
+    // Pairs of (upper bound, count); the last bucket has no upper bound
+    pub fn buckets(&self) -> Vec<(Option<Duration>, u64)> {
+        self.counts.iter()
+            .enumerate()
+            .map(|(index, count)| (self.bounds.get(index).copied(), *count))
+            .collect()
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CommandStats {
+    pub latency: Histogram,
+    pub timeouts: u64,
+    pub hamlib_errors: u64,
+    pub return_codes: BTreeMap<i32, u64>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ClientStats {
+    pub commands: BTreeMap<String, CommandStats>,
+}
+
+impl ClientStats {
+    pub fn command(&self, name: &str) -> Option<&CommandStats> {
+        self.commands.get(name)
+    }
+
+    pub(crate) fn record_reply(&mut self, command: &str, latency: Duration, result: &Result<String, RigCtlError>) {
+        let name = command_name(command);
+        let stats = self.commands.entry(name.to_string()).or_default();
+        stats.latency.record(latency);
+
+        #[cfg(feature = "metrics")]
+        metrics::histogram!("hamlib_client_command_latency_seconds", "command" => name.to_string())
+            .record(latency.as_secs_f64());
+
+        if let Err(RigCtlError::HamlibError(code)) = result {
+            stats.hamlib_errors += 1;
+            *stats.return_codes.entry(code.code()).or_default() += 1;
+
+            #[cfg(feature = "metrics")]
+            metrics::counter!("hamlib_client_command_errors_total", "command" => name.to_string(), "code" => code.code().to_string())
+                .increment(1);
+        }
+    }
+
+    pub(crate) fn record_timeout(&mut self, command: &str) {
+        let name = command_name(command);
+        self.commands.entry(name.to_string()).or_default().timeouts += 1;
+
+        #[cfg(feature = "metrics")]
+        metrics::counter!("hamlib_client_command_timeouts_total", "command" => name.to_string())
+            .increment(1);
+    }
+}
+
+fn command_name(command: &str) -> &str {
+    command.split_whitespace().next().unwrap_or(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ReturnCode;
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Histogram::default();
+        assert_eq!(histogram.mean(), None);
+        assert_eq!(histogram.quantile(0.5), None);
+
+        histogram.record(Duration::from_millis(3));
+        histogram.record(Duration::from_millis(7));
+        histogram.record(Duration::from_millis(40));
+        histogram.record(Duration::from_secs(10));
+
+        assert_eq!(histogram.count(), 4);
+        assert_eq!(histogram.min(), Some(Duration::from_millis(3)));
+        assert_eq!(histogram.max(), Some(Duration::from_secs(10)));
+        assert_eq!(histogram.quantile(0.5), Some(Duration::from_millis(10)));
+        assert_eq!(histogram.quantile(1.0), None);
+        assert_eq!(histogram.buckets().last(), Some(&(None, 1)));
+    }
+
+    #[test]
+    fn test_mean_past_u32_samples() {
+        let histogram = Histogram { count: 1 << 32, sum: Duration::from_secs(3 << 32), ..Histogram::default() };
+        assert_eq!(histogram.mean(), Some(Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn test_record_by_command_name() {
+        let mut stats = ClientStats::default();
+        stats.record_reply("get_freq VFOA", Duration::from_millis(5), &Ok(String::new()));
+        stats.record_reply("get_freq VFOB", Duration::from_millis(5), &Err(RigCtlError::HamlibError(ReturnCode::ETIMEOUT)));
+        stats.record_timeout("get_freq VFOA");
+
+        let freq = stats.command("get_freq").unwrap();
+        assert_eq!(freq.latency.count(), 2);
+        assert_eq!(freq.hamlib_errors, 1);
+        assert_eq!(freq.return_codes.get(&-5), Some(&1));
+        assert_eq!(freq.timeouts, 1);
+        assert!(stats.command("get_mode").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_timeout_charged_to_unanswered_commands() {
+        use crate::RigCtlClient;
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let (stream, rig) = tokio::io::duplex(1024);
+        tokio::spawn(async move {
+            let (reader, mut writer) = tokio::io::split(rig);
+            let mut lines = BufReader::new(reader).lines();
+            lines.next_line().await.unwrap();
+            writer.write_all(b"get_freq: VFOA|Frequency: 14074000|RPRT 0\n").await.unwrap();
+            while let Ok(Some(_)) = lines.next_line().await {}
+        });
+
+        let mut client = RigCtlClient::from_stream(stream, Some(100));
+        let commands = vec!["get_freq VFOA".to_string(), "get_mode VFOA".to_string()];
+        assert!(matches!(client.execute_commands(&commands).await, Err(RigCtlError::CommunicationTimeout)));
+
+        let stats = client.stats();
+        assert_eq!(stats.command("get_freq").unwrap().timeouts, 0);
+        assert_eq!(stats.command("get_freq").unwrap().latency.count(), 1);
+        assert_eq!(stats.command("get_mode").unwrap().timeouts, 1);
+    }
+}
@@ -0,0 +1,304 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use crate::protocol;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::mpsc;
+use std::task::{Context, Poll};
+use std::thread;
+use std::thread::JoinHandle;
+use time::OffsetDateTime;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    #[serde(with = "time::serde::rfc3339")]
+    pub timestamp: OffsetDateTime,
+    pub direction: Direction,
+    pub data: String,
+}
+
+impl Entry {
+    pub fn new(direction: Direction, data: &[u8]) -> Self {
+        Self {
+            timestamp: OffsetDateTime::now_utc(),
+            direction,
+            data: protocol::redact(&String::from_utf8_lossy(data)),
+        }
+    }
+}
+
+pub fn read_transcript<R: BufRead>(reader: R) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        entries.push(serde_json::from_str(&line)?);
+    }
+
+    Ok(entries)
+}
+
+// Appends one JSON object per line; passwords are redacted before they hit
+// the file so that transcripts can be attached to bug reports as they are
+//
+// Entries are handed to a writer thread, so that recording never blocks
+// the runtime on file I/O; the writer flushes whenever it catches up
+pub struct TranscriptRecorder {
+    entries: Option<mpsc::Sender<Entry>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl TranscriptRecorder {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::from_writer(BufWriter::new(File::create(path)?)))
+    }
+
+    pub fn from_writer<W: Write + Send + 'static>(writer: W) -> Self {
+        let (entries, receiver) = mpsc::channel();
+        let writer = thread::spawn(move || write_entries(writer, receiver));
+
+        Self {
+            entries: Some(entries),
+            writer: Some(writer),
+        }
+    }
+
+    pub fn record(&mut self, direction: Direction, data: &[u8]) {
+        let sent = self.entries.as_ref()
+            .map(|entries| entries.send(Entry::new(direction, data)).is_ok())
+            .unwrap_or_default();
+        if !sent {
+            log::warn!("Unable to write transcript entry: writer stopped");
+        }
+    }
+}
+
+impl Drop for TranscriptRecorder {
+    // Waits for the pending entries to be written
+    fn drop(&mut self) {
+        self.entries = None;
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+fn write_entries<W: Write>(mut writer: W, entries: mpsc::Receiver<Entry>) {
+    while let Ok(entry) = entries.recv() {
+        let mut result = write_entry(&mut writer, &entry);
+        while result.is_ok() {
+            match entries.try_recv() {
+                Ok(entry) => result = write_entry(&mut writer, &entry),
+                Err(_) => break,
+            }
+        }
+
+        if let Err(e) = result.and_then(|_| writer.flush()) {
+            log::warn!("Unable to write transcript entry: {}", e);
+        }
+    }
+}
+
+fn write_entry<W: Write>(writer: &mut W, entry: &Entry) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, entry)?;
+    writer.write_all(b"\n")
+}
+
+// Plays the received side of a transcript back to the client, checking that
+// the client sends the same data as in the recorded session
+#[derive(Debug)]
+pub struct ReplayTransport {
+    entries: VecDeque<Entry>,
+    sent: Vec<u8>,
+    received: VecDeque<u8>,
+}
+
+impl ReplayTransport {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::new(read_transcript(BufReader::new(File::open(path)?))?))
+    }
+
+    pub fn new(entries: Vec<Entry>) -> Self {
+        Self {
+            entries: entries.into(),
+            sent: Vec::new(),
+            received: VecDeque::new(),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.entries.is_empty() && self.received.is_empty()
+    }
+
+    fn accept(&mut self, data: &[u8]) -> io::Result<()> {
+        let expected = match self.entries.front() {
+            Some(entry) if entry.direction == Direction::Sent => entry.data.clone(),
+            _ => return Err(mismatch(format!("unexpected data sent: {:?}", String::from_utf8_lossy(data)))),
+        };
+
+        self.sent.extend_from_slice(data);
+        let actual = protocol::redact(&String::from_utf8_lossy(&self.sent));
+
+        if actual == expected {
+            self.entries.pop_front();
+            self.sent.clear();
+            return Ok(());
+        }
+
+        match expected.starts_with(actual.as_str()) {
+            true => Ok(()),
+            false => Err(mismatch(format!("expected {:?} to be sent, got {:?}", expected, actual))),
+        }
+    }
+}
+
+impl AsyncRead for ReplayTransport {
+    fn poll_read(mut self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        if self.received.is_empty() {
+            match self.entries.front() {
+                Some(entry) if entry.direction == Direction::Received => {
+                    let entry = self.entries.pop_front().unwrap();
+                    self.received.extend(entry.data.into_bytes());
+                }
+                Some(entry) => {
+                    let message = format!("expected {:?} to be sent before reading", entry.data);
+                    return Poll::Ready(Err(mismatch(message)));
+                }
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+
+        let count = buf.remaining().min(self.received.len());
+        let data = self.received.drain(..count).collect::<Vec<u8>>();
+        buf.put_slice(&data);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for ReplayTransport {
+    fn poll_write(mut self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Poll::Ready(self.accept(buf).map(|_| buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn mismatch(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Transcript mismatch: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfo::VFO;
+    use crate::RigCtlClient;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_recorder_redacts_password() {
+        let buffer = SharedBuffer::default();
+        let mut recorder = TranscriptRecorder::from_writer(buffer.clone());
+        recorder.record(Direction::Sent, b"|\\password secret\n");
+        recorder.record(Direction::Received, b"password: ***|RPRT 0\n");
+        drop(recorder);
+
+        let data = buffer.0.lock().unwrap().clone();
+        let entries = read_transcript(data.as_slice()).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].direction, Direction::Sent);
+        assert_eq!(entries[0].data, "|\\password ***\n");
+    }
+
+    #[test]
+    fn test_writer_keeps_entries_after_error() {
+        struct FailingOnce(bool, Vec<u8>);
+
+        impl Write for FailingOnce {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                match std::mem::replace(&mut self.0, false) {
+                    true => Err(io::Error::other("disk full")),
+                    false => self.1.write(buf),
+                }
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        sender.send(Entry::new(Direction::Sent, b"|\\get_freq VFOA\n")).unwrap();
+        sender.send(Entry::new(Direction::Sent, b"|\\get_mode VFOA\n")).unwrap();
+        drop(sender);
+
+        let mut writer = FailingOnce(true, Vec::new());
+        write_entries(&mut writer, receiver);
+
+        let entries = read_transcript(writer.1.as_slice()).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].data, "|\\get_mode VFOA\n");
+    }
+
+    #[tokio::test]
+    async fn test_replay() {
+        let entries = vec![
+            Entry::new(Direction::Sent, b"|\\get_freq VFOA\n"),
+            Entry::new(Direction::Received, b"get_freq: VFOA|Frequency: 14074000|RPRT 0\n"),
+            Entry::new(Direction::Sent, b"|\\get_freq VFOB\n"),
+        ];
+
+        let mut client = RigCtlClient::from_stream(ReplayTransport::new(entries), Some(100));
+        assert_eq!(client.get_freq(VFO::VFOA).await.unwrap().frequency, 14074000);
+        assert!(client.get_mode(VFO::VFOA).await.is_err());
+    }
+}
@@ -0,0 +1,138 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use crate::connection;
+use crate::connection::TcpOptions;
+use crate::error::RigCtlError;
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use std::future::Future;
+use std::io;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Transport for T {}
+
+pub type BoxedTransport = Box<dyn Transport>;
+
+pub type ConnectFuture<'a> = Pin<Box<dyn Future<Output=io::Result<BoxedTransport>> + Send + 'a>>;
+
+pub trait Connector: Send + Sync {
+    fn connect(&self) -> ConnectFuture<'_>;
+}
+
+#[derive(Clone)]
+pub enum Endpoint {
+    Tcp { host: String, port: u16, options: TcpOptions },
+    #[cfg(unix)]
+    Unix(PathBuf),
+    Custom(Arc<dyn Connector>),
+    Stream,
+}
+
+impl Endpoint {
+    pub(crate) async fn open(&self) -> Result<BoxedTransport, RigCtlError> {
+        match self {
+            Endpoint::Tcp { host, port, options } => {
+                let stream = connection::connect_tcp(host, *port, options).await?;
+                Ok(Box::new(stream))
+            }
+            #[cfg(unix)]
+            Endpoint::Unix(path) => {
+                let stream = tokio::net::UnixStream::connect(path).await?;
+                Ok(Box::new(stream))
+            }
+            Endpoint::Custom(connector) => Ok(connector.connect().await?),
+            Endpoint::Stream => Err(RigCtlError::ConnectionError("User-provided stream cannot be reopened".to_string())),
+        }
+    }
+}
+
+impl Display for Endpoint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Tcp { host, port, .. } => { write!(f, "{}:{}", host, port) }
+            #[cfg(unix)]
+            Endpoint::Unix(path) => { write!(f, "unix:{}", path.display()) }
+            Endpoint::Custom(_) => { write!(f, "custom connector") }
+            Endpoint::Stream => { write!(f, "user-provided stream") }
+        }
+    }
+}
+
+impl Debug for Endpoint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Endpoint({})", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::get_freq;
+    use crate::vfo::VFO;
+    use crate::RigCtlClient;
+    use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+
+    async fn serve<S: AsyncRead + AsyncWrite + Unpin>(stream: S) {
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut lines = BufReader::new(reader).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let reply = match line.as_str() {
+                r"|\get_freq VFOA" => "get_freq: VFOA|Frequency: 7074000|RPRT 0",
+                _ => "RPRT -4",
+            };
+            writer.write_all(format!("{}\n", reply).as_bytes()).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_duplex_transport() {
+        let (client_side, server_side) = tokio::io::duplex(1024);
+        tokio::spawn(serve(server_side));
+
+        let mut client = RigCtlClient::from_stream(client_side, None);
+        assert!(client.is_connected());
+
+        let actual = client.get_freq(VFO::VFOA).await.unwrap();
+        assert_eq!(actual, get_freq::Response { query_vfo: VFO::VFOA, frequency: 7074000u64 });
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_transport() {
+        let path = std::env::temp_dir().join(format!("hamlib-client-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            serve(stream).await;
+        });
+
+        let mut client = RigCtlClient::new_unix(&path, None);
+        client.connect().await.unwrap();
+
+        let actual = client.get_freq(VFO::VFOA).await.unwrap();
+        assert_eq!(actual.frequency, 7074000u64);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
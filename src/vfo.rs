@@ -19,7 +19,7 @@ use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::str::FromStr;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum VFO {
     VFOA,
     VFOB,
@@ -34,13 +34,17 @@ pub enum VFO {
     None,
 }
 
+impl VFO {
+    pub const ALL: [VFO; 11] = [VFO::VFOA, VFO::VFOB, VFO::VFOC, VFO::CurrVfo, VFO::VFO, VFO::MEM, VFO::Main, VFO::Sub, VFO::TX, VFO::RX, VFO::None];
+}
+
 impl Display for VFO {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match self {
             VFO::VFOA => write!(f, "VFOA"),
             VFO::VFOB => write!(f, "VFOB"),
             VFO::VFOC => write!(f, "VFOC"),
-            VFO::CurrVfo => write!(f, "currVfo"),
+            VFO::CurrVfo => write!(f, "currVFO"),
             VFO::VFO => write!(f, "VFO"),
             VFO::MEM => write!(f, "MEM"),
             VFO::Main => write!(f, "Main"),
@@ -0,0 +1,352 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+
+use crate::error::RigCtlError;
+pub use crate::event::RigEvent;
+use crate::handle::RigCtlHandle;
+use crate::snapshot::RigStateSnapshot;
+use crate::vfo::VFO;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio::time::{interval, MissedTickBehavior};
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+
+const DEFAULT_CAPACITY: usize = 64;
+
+// Frequency and PTT are polled every `frequency_interval`, mode, VFO and
+// split every `mode_interval`
+#[derive(Debug, Clone)]
+pub struct WatchConfig {
+    pub vfo: VFO,
+    pub frequency_interval: Duration,
+    pub mode_interval: Duration,
+    pub capacity: usize,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            vfo: VFO::CurrVfo,
+            frequency_interval: Duration::from_millis(250),
+            mode_interval: Duration::from_secs(1),
+            capacity: DEFAULT_CAPACITY,
+        }
+    }
+}
+
+pub struct RigWatcher {
+    events: broadcast::Sender<RigEvent>,
+    task: JoinHandle<()>,
+}
+
+impl RigWatcher {
+    pub fn spawn(handle: RigCtlHandle) -> Self {
+        Self::spawn_with_config(handle, WatchConfig::default())
+    }
+
+    pub fn spawn_with_config(handle: RigCtlHandle, config: WatchConfig) -> Self {
+        Self::start(handle, config, None)
+    }
+
+    // Changes the rig pushes on the handle's connection in transceive mode
+    // are always merged; this also merges those published by another
+    // source, typically `multicast::MulticastListener::subscribe_events`, so
+    // that they show up without waiting for the next poll
+    pub fn spawn_with_events(handle: RigCtlHandle, config: WatchConfig, pushed: broadcast::Receiver<RigEvent>) -> Self {
+        Self::start(handle, config, Some(pushed))
+    }
+
+    fn start(handle: RigCtlHandle, config: WatchConfig, pushed: Option<broadcast::Receiver<RigEvent>>) -> Self {
+        let (events, _) = broadcast::channel(config.capacity.max(1));
+        let transceive = handle.subscribe_rig_events();
+        let task = tokio::spawn(run(handle, config, events.clone(), transceive, pushed));
+
+        Self { events, task }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<RigEvent> {
+        self.events.subscribe()
+    }
+
+    // Events missed by a lagging consumer are skipped
+    pub fn stream(&self) -> impl Stream<Item = RigEvent> {
+        BroadcastStream::new(self.events.subscribe()).filter_map(Result::ok)
+    }
+}
+
+impl Drop for RigWatcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+struct Watch {
+    handle: RigCtlHandle,
+    vfo: VFO,
+    events: broadcast::Sender<RigEvent>,
+    state: RigStateSnapshot,
+    connected: bool,
+}
+
+impl Watch {
+    async fn poll_frequency(&mut self) -> Result<(), RigCtlError> {
+        if let Some(response) = self.check(self.handle.get_freq(self.vfo).await)? {
+            if update(&mut self.state.frequency, response.frequency) {
+                self.publish(RigEvent::FrequencyChanged(response.frequency));
+            }
+        }
+
+        if let Some(response) = self.check(self.handle.get_ptt(self.vfo).await)? {
+            if update(&mut self.state.ptt, response.ptt) {
+                self.publish(RigEvent::PttChanged(response.ptt));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn poll_mode(&mut self) -> Result<(), RigCtlError> {
+        if let Some(response) = self.check(self.handle.get_mode(self.vfo).await)? {
+            let mode = update(&mut self.state.mode, response.mode);
+            let passband = update(&mut self.state.passband, response.passband);
+            if mode || passband {
+                self.publish(RigEvent::ModeChanged { mode: response.mode, passband: response.passband });
+            }
+        }
+
+        if let Some(response) = self.check(self.handle.get_vfo().await)? {
+            if update(&mut self.state.vfo, response.vfo) {
+                self.publish(RigEvent::VfoChanged(response.vfo));
+            }
+        }
+
+        if let Some(response) = self.check(self.handle.get_split_vfo().await)? {
+            let split = update(&mut self.state.split, response.split);
+            let tx_vfo = update(&mut self.state.tx_vfo, response.tx_vfo);
+            if split || tx_vfo {
+                self.publish(RigEvent::SplitChanged { split: response.split, tx_vfo: response.tx_vfo });
+            }
+        }
+
+        Ok(())
+    }
+
+    // Changes pushed by the rig or another source, deduplicated against the
+    // polled state
+    fn apply(&mut self, event: RigEvent) {
+        let changed = match &event {
+            RigEvent::FrequencyChanged(frequency) => update(&mut self.state.frequency, *frequency),
+            RigEvent::ModeChanged { mode, passband } => {
+                let mode = update(&mut self.state.mode, *mode);
+                update(&mut self.state.passband, *passband) || mode
+            }
+            RigEvent::VfoChanged(vfo) => update(&mut self.state.vfo, *vfo),
+            RigEvent::SplitChanged { split, tx_vfo } => {
+                let split = update(&mut self.state.split, *split);
+                update(&mut self.state.tx_vfo, *tx_vfo) || split
+            }
+            RigEvent::PttChanged(ptt) => update(&mut self.state.ptt, *ptt),
+            RigEvent::Disconnected => {
+                self.disconnected();
+                false
+            }
+        };
+
+        if changed {
+            self.publish(event);
+        }
+    }
+
+    // Connection failures abort the poll, anything else (usually a command
+    // the rig does not support) only skips the field
+    fn check<T>(&mut self, result: Result<T, RigCtlError>) -> Result<Option<T>, RigCtlError> {
+        match result {
+            Ok(value) => {
+                self.connected = true;
+                Ok(Some(value))
+            }
+            Err(e @ (RigCtlError::ConnectionError(_) | RigCtlError::NotConnected)) => Err(e),
+            Err(e) => {
+                log::trace!("Watched command failed: {}", e);
+                Ok(None)
+            }
+        }
+    }
+
+    fn disconnected(&mut self) {
+        if self.connected {
+            self.connected = false;
+            // Forget the last state, so that everything is published again
+            // once the rig is back
+            self.state = RigStateSnapshot::empty(OffsetDateTime::now_utc());
+            self.publish(RigEvent::Disconnected);
+        }
+    }
+
+    fn publish(&mut self, event: RigEvent) {
+        self.state.timestamp = OffsetDateTime::now_utc();
+        let _ = self.events.send(event);
+    }
+}
+
+fn update<T: PartialEq>(field: &mut Option<T>, value: T) -> bool {
+    match field {
+        Some(current) if *current == value => false,
+        _ => {
+            *field = Some(value);
+            true
+        }
+    }
+}
+
+async fn run(handle: RigCtlHandle, config: WatchConfig, events: broadcast::Sender<RigEvent>, transceive: broadcast::Receiver<RigEvent>, mut pushed: Option<broadcast::Receiver<RigEvent>>) {
+    let mut transceive = Some(transceive);
+    let mut watch = Watch {
+        handle,
+        vfo: config.vfo,
+        events,
+        state: RigStateSnapshot::empty(OffsetDateTime::now_utc()),
+        connected: true,
+    };
+
+    let mut frequency = interval(config.frequency_interval);
+    frequency.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut mode = interval(config.mode_interval);
+    mode.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        let result = tokio::select! {
+            _ = frequency.tick() => watch.poll_frequency().await,
+            _ = mode.tick() => watch.poll_mode().await,
+            event = next_pushed(&mut transceive) => {
+                if let Some(event) = event {
+                    watch.apply(event);
+                }
+                Ok(())
+            }
+            event = next_pushed(&mut pushed) => {
+                if let Some(event) = event {
+                    watch.apply(event);
+                }
+                Ok(())
+            }
+        };
+
+        if let Err(e) = result {
+            log::debug!("Watched rig disconnected: {}", e);
+            watch.disconnected();
+        }
+    }
+}
+
+// Never resolves without a pushed source; a closed source is dropped
+async fn next_pushed(pushed: &mut Option<broadcast::Receiver<RigEvent>>) -> Option<RigEvent> {
+    let receiver = match pushed {
+        Some(receiver) => receiver,
+        None => return std::future::pending().await,
+    };
+
+    match receiver.recv().await {
+        Ok(event) => Some(event),
+        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+            log::debug!("Skipped {} pushed events", skipped);
+            None
+        }
+        Err(broadcast::error::RecvError::Closed) => {
+            *pushed = None;
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mode::Mode;
+    use crate::mock::MockServer;
+    use tokio::time::timeout;
+
+    // Waits until all the expected events have been seen, in any order
+    async fn expect_events(events: &mut broadcast::Receiver<RigEvent>, mut expected: Vec<RigEvent>) {
+        let wait = async {
+            while !expected.is_empty() {
+                let event = events.recv().await.unwrap();
+                expected.retain(|expected| *expected != event);
+            }
+        };
+        timeout(Duration::from_secs(2), wait).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_watcher_publishes_changes() {
+        let server = MockServer::start().await.unwrap();
+        let handle = RigCtlHandle::spawn(server.client());
+        handle.connect().await.unwrap();
+
+        let config = WatchConfig {
+            frequency_interval: Duration::from_millis(10),
+            mode_interval: Duration::from_millis(20),
+            ..WatchConfig::default()
+        };
+        let watcher = RigWatcher::spawn_with_config(handle, config);
+        let mut events = watcher.subscribe();
+
+        expect_events(&mut events, vec![
+            RigEvent::FrequencyChanged(14074000),
+            RigEvent::ModeChanged { mode: Mode::USB, passband: 3000 },
+            RigEvent::VfoChanged(VFO::VFOA),
+        ]).await;
+
+        server.update_rig(|rig| rig.vfos.get_mut(&VFO::VFOA).unwrap().frequency = 7074000);
+        expect_events(&mut events, vec![RigEvent::FrequencyChanged(7074000)]).await;
+
+        server.disconnect_after(Some(0));
+        expect_events(&mut events, vec![RigEvent::Disconnected]).await;
+    }
+
+    #[tokio::test]
+    async fn test_watcher_merges_pushed_events() {
+        let server = MockServer::start().await.unwrap();
+        let handle = RigCtlHandle::spawn(server.client());
+        handle.connect().await.unwrap();
+
+        let (pushed, receiver) = broadcast::channel(8);
+        let watcher = RigWatcher::spawn_with_events(handle, WatchConfig::default(), receiver);
+        let mut events = watcher.subscribe();
+        expect_events(&mut events, vec![RigEvent::FrequencyChanged(14074000)]).await;
+
+        pushed.send(RigEvent::FrequencyChanged(14074000)).unwrap();
+        pushed.send(RigEvent::FrequencyChanged(7074000)).unwrap();
+        expect_events(&mut events, vec![RigEvent::FrequencyChanged(7074000)]).await;
+    }
+
+    #[tokio::test]
+    async fn test_watcher_stream() {
+        let server = MockServer::start().await.unwrap();
+        let handle = RigCtlHandle::spawn(server.client());
+        handle.connect().await.unwrap();
+
+        let watcher = RigWatcher::spawn(handle);
+        let mut stream = Box::pin(watcher.stream());
+
+        let event = timeout(Duration::from_secs(2), stream.next()).await.unwrap();
+        assert!(event.is_some());
+    }
+}